@@ -0,0 +1,264 @@
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+use crate::catalog::{catalog, Uom};
+use crate::events::{bus, EventFilter};
+use crate::goods_transportation::GoodsTransportation;
+use crate::inventory_management::InventoryManagement;
+use crate::stock_rpc::StockService;
+use crate::order_processing::Order;
+use crate::order_registry::{registry, OrderStatus};
+use crate::rabbitmq;
+
+#[derive(Debug, Deserialize)]
+struct NewOrder {
+    code: String,
+    quantity: i32,
+    #[serde(default)]
+    uom: Uom,
+    order_type: String,
+}
+
+impl NewOrder {
+    fn validate(&self) -> Result<(), &'static str> {
+        if self.quantity <= 0 {
+            return Err("quantity must be positive");
+        }
+        if self.order_type != "supply" && self.order_type != "offload" {
+            return Err("order_type must be \"supply\" or \"offload\"");
+        }
+        Ok(())
+    }
+
+    fn into_order(self) -> Order {
+        Order::with_uom(self.code, self.quantity, self.uom, self.order_type)
+    }
+}
+
+// What one process serves; endpoints of systems running elsewhere answer 404
+pub struct ApiParts {
+    pub service: &'static str,
+    pub orders: Option<OrderIntake>,
+    pub inventory: Option<Arc<InventoryManagement>>,
+    pub transport: Option<Arc<GoodsTransportation>>,
+}
+
+// Where accepted orders go and how their stock is reserved
+pub struct OrderIntake {
+    pub queue: String,
+    pub stock: Arc<dyn StockService>,
+}
+
+// Serve the local HTTP API for ERP and internal tools:
+//   GET  /health                       service name, broker reachability and uptime
+//   GET  /items, /items/{code}         stock and movement history
+//   GET  /racks                        rack contents
+//   GET  /forklifts                    forklift status and current job
+//   GET  /orders/{id}                  order status, by id or display number
+//   POST /orders                       submit a supply or offload order
+//   POST /orders/batch                 submit an array of orders, published in one batch
+//   POST /orders/{id}/cancel           cancel an order not yet in transit
+//   GET  /events?type=&item=&forklift= live Server-Sent Events stream
+pub fn serve(addr: &str, parts: ApiParts) {
+    let server = match Server::http(addr) {
+        Ok(server) => server,
+        Err(e) => {
+            error!("api", "Failed to start API on {}: {}", addr, e);
+            return;
+        }
+    };
+    info!("api", "API available at http://{}", addr);
+    let started = Instant::now();
+    thread::spawn(move || {
+        for mut request in server.incoming_requests() {
+            if *request.method() == Method::Get && request.url().split('?').next() == Some("/events") {
+                let filter = event_filter(request.url());
+                thread::spawn(move || stream_events(request, filter));   // Streams stay open, so give each its own thread
+                continue;
+            }
+            let (status, body) = handle(&mut request, &parts, started);
+            let response = Response::from_string(body.to_string())
+                .with_status_code(status)
+                .with_header("Content-Type: application/json".parse::<Header>().unwrap());
+            if let Err(e) = request.respond(response) {
+                error!("api", "Failed to respond to request: {}", e);
+            }
+        }
+    });
+}
+
+fn handle(request: &mut Request, parts: &ApiParts, started: Instant) -> (u16, Value) {
+    let path = request.url().split('?').next().unwrap_or("").to_string();
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    debug!("api", "{} {}", request.method(), path);
+
+    match (request.method(), segments.as_slice()) {
+        (Method::Get, ["health"]) => {
+            let broker = rabbitmq::ping().is_ok();
+            let status = if broker { "ok" } else { "broker_unreachable" };
+            (if broker { 200 } else { 503 }, json!({ "service": parts.service, "status": status, "uptime_secs": started.elapsed().as_secs() }))
+        }
+        (Method::Get, ["items"]) => {
+            let Some(inventory_management) = &parts.inventory else { return not_served(parts) };
+            (200, json!(inventory_management.inventory_snapshot()))
+        }
+        (Method::Get, ["items", code]) => {
+            let Some(inventory_management) = &parts.inventory else { return not_served(parts) };
+            match inventory_management.item(code) {
+                Some(item) => (200, json!({ "item": item, "catalog": catalog().get(code), "history": inventory_management.item_history(code) })),
+                None => not_found(&format!("Item {} not found", code)),
+            }
+        }
+        (Method::Get, ["racks"]) => {
+            let Some(inventory_management) = &parts.inventory else { return not_served(parts) };
+            (200, json!(inventory_management.rack_snapshot()))
+        }
+        (Method::Get, ["forklifts"]) => {
+            let Some(goods_transportation) = &parts.transport else { return not_served(parts) };
+            (200, json!(goods_transportation.forklift_status()))
+        }
+        (Method::Get, ["orders", key]) => {
+            if parts.orders.is_none() {
+                return not_served(parts);
+            }
+            match registry().find(key) {
+                Some(record) => (200, json!(record)),
+                None => not_found(&format!("Order {} not found", key)),
+            }
+        }
+        (Method::Post, ["orders"]) => {
+            let Some(intake) = &parts.orders else { return not_served(parts) };
+            let mut body = String::new();
+            if let Err(e) = request.as_reader().read_to_string(&mut body) {
+                return (400, json!({ "error": e.to_string() }));
+            }
+            let new_order = match serde_json::from_str::<NewOrder>(&body) {
+                Ok(new_order) => new_order,
+                Err(e) => return (400, json!({ "error": format!("Invalid order: {}", e) })),
+            };
+            if let Err(e) = new_order.validate() {
+                return (400, json!({ "error": e }));
+            }
+            let order = new_order.into_order();
+            let id = order.id.clone();
+            let status = match order.submit(intake.stock.as_ref(), &intake.queue) {
+                OrderStatus::Dispatched => 201,
+                OrderStatus::Declined => 422,
+                _ => 502,
+            };
+            (status, json!(registry().get(&id)))
+        }
+        (Method::Post, ["orders", "batch"]) => {
+            let Some(intake) = &parts.orders else { return not_served(parts) };
+            let mut body = String::new();
+            if let Err(e) = request.as_reader().read_to_string(&mut body) {
+                return (400, json!({ "error": e.to_string() }));
+            }
+            let new_orders = match serde_json::from_str::<Vec<NewOrder>>(&body) {
+                Ok(new_orders) => new_orders,
+                Err(e) => return (400, json!({ "error": format!("Invalid orders: {}", e) })),
+            };
+            // All or nothing: no order is created unless every one is valid
+            for (i, new_order) in new_orders.iter().enumerate() {
+                if let Err(e) = new_order.validate() {
+                    return (400, json!({ "error": format!("Order {}: {}", i, e) }));
+                }
+            }
+            let orders: Vec<Order> = new_orders.into_iter().map(NewOrder::into_order).collect();
+            let ids: Vec<String> = orders.iter().map(|order| order.id.clone()).collect();
+            Order::submit_batch(orders, intake.stock.as_ref(), &intake.queue);
+            (200, json!(ids.iter().filter_map(|id| registry().get(id)).collect::<Vec<_>>()))
+        }
+        (Method::Post, ["orders", key, "cancel"]) => {
+            let Some(intake) = &parts.orders else { return not_served(parts) };
+            let id = match registry().find(key) {
+                Some(record) => record.order.id,
+                None => return not_found(&format!("Order {} not found", key)),
+            };
+            match registry().cancel(&id) {
+                Ok(record) => {
+                    info!("api", order = id, "Order cancelled");
+                    if let Err(e) = intake.stock.release(&id) {
+                        warn!("api", order = id, "Failed to release reserved stock: {}", e);  // Expires on its own
+                    }
+                    (200, json!(record))
+                }
+                Err(e) if registry().get(&id).is_none() => not_found(&e),
+                Err(e) => (409, json!({ "error": e })),
+            }
+        }
+        _ => not_found("Not Found"),
+    }
+}
+
+fn not_found(message: &str) -> (u16, Value) {
+    (404, json!({ "error": message }))
+}
+
+fn not_served(parts: &ApiParts) -> (u16, Value) {
+    not_found(&format!("Not served by {}", parts.service))
+}
+
+// Write events to the client as Server-Sent Events until it disconnects
+fn stream_events(request: Request, filter: EventFilter) {
+    let events = bus().subscribe();
+    let mut writer = request.into_writer();
+    let headers = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n";
+    if writer.write_all(headers.as_bytes()).and_then(|_| writer.flush()).is_err() {
+        return;
+    }
+    debug!("api", "Event stream opened with filter {:?}", filter);
+    loop {
+        let chunk = match events.recv_timeout(Duration::from_secs(15)) {
+            Ok((ts_us, event)) if filter.matches(&event) => format!("event: {}\ndata: {}\n\n", event.kind(), event.to_json(ts_us)),
+            Ok(_) => continue,
+            Err(RecvTimeoutError::Timeout) => ": keepalive\n\n".to_string(),
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        if writer.write_all(chunk.as_bytes()).and_then(|_| writer.flush()).is_err() {
+            break; // Client went away
+        }
+    }
+    debug!("api", "Event stream closed");
+}
+
+// Comma separated filters, e.g. /events?type=rack_changed,inventory_updated&item=001
+fn event_filter(url: &str) -> EventFilter {
+    let mut filter = EventFilter::default();
+    let query = url.split_once('?').map(|(_, q)| q).unwrap_or("");
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let values = url_decode(value).split(',').filter(|v| !v.is_empty()).map(|v| v.to_string()).collect();
+        match key {
+            "type" => filter.kinds = values,
+            "item" => filter.item_codes = values,
+            "forklift" => filter.forklifts = values,
+            _ => {}
+        }
+    }
+    filter
+}
+
+fn url_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() && bytes[i + 1].is_ascii_hexdigit() && bytes[i + 2].is_ascii_hexdigit() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap();
+                decoded.push(u8::from_str_radix(hex, 16).unwrap());
+                i += 2;
+            }
+            b => decoded.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
@@ -0,0 +1,244 @@
+use std::sync::OnceLock;
+use futures_util::StreamExt;
+use lapin::options::{BasicAckOptions, BasicConsumeOptions, BasicNackOptions, BasicPublishOptions, BasicQosOptions, ConfirmSelectOptions, QueueDeclareOptions};
+use lapin::publisher_confirm::Confirmation;
+use lapin::types::{AMQPValue, FieldTable};
+use lapin::{BasicProperties, Channel, Connection, ConnectionProperties, Consumer};
+use serde::de::DeserializeOwned;
+use tokio::runtime::Runtime;
+use tokio::sync::Mutex;
+use ulid::Ulid;
+use crate::codec::Codec;
+use crate::latency::now_micros;
+use crate::metrics::metrics;
+use crate::rabbitmq::{amqp_url, dead_letter_queue, retry_policy, PublishError, Settle, CONFIRM_TIMEOUT};
+
+// Async counterpart of rabbitmq.rs on lapin and tokio. Consumers share one runtime and wait on
+// sockets and timers instead of each blocking a thread; the blocking API stays for everything else.
+
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+static PUBLISHER: OnceLock<Publisher> = OnceLock::new();
+
+pub fn runtime() -> &'static Runtime {
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread().thread_name("wms-async").enable_all().build()
+            .expect("Failed to start async runtime")
+    })
+}
+
+async fn connect() -> lapin::Result<Connection> {
+    Connection::connect(amqp_url(), ConnectionProperties::default()).await
+}
+
+// One long-lived confirm channel for all async publishes, reopened after a failure
+struct Publisher {
+    channel: Mutex<Option<(Connection, Channel)>>,
+}
+
+impl Publisher {
+    async fn channel(&self) -> lapin::Result<Channel> {
+        let mut slot = self.channel.lock().await;
+        if let Some((_, channel)) = slot.as_ref().filter(|(_, channel)| channel.status().connected()) {
+            return Ok(channel.clone());
+        }
+        let connection = connect().await?;
+        let channel = connection.create_channel().await?;
+        channel.confirm_select(ConfirmSelectOptions::default()).await?;
+        *slot = Some((connection, channel.clone()));
+        Ok(channel)
+    }
+
+    async fn reset(&self) {
+        self.channel.lock().await.take();
+    }
+}
+
+fn publisher() -> &'static Publisher {
+    PUBLISHER.get_or_init(|| Publisher { channel: Mutex::new(None) })
+}
+
+// As rabbitmq::send_message: confirmed, persistent and mandatory, retried with backoff
+pub async fn send_message(queue_addr: &str, body: &[u8], content_type: &str) -> Result<(), PublishError> {
+    let properties = BasicProperties::default().with_content_type(content_type.into());
+    let policy = retry_policy();
+    let mut attempt = 1;
+    loop {
+        match publish(queue_addr, body, properties.clone()).await {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt < policy.max_attempts => {
+                warn!("rabbitmq", "Publish to {} failed (attempt {}/{}): {}", queue_addr, attempt, policy.max_attempts, e);
+                tokio::time::sleep(policy.backoff(attempt)).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+async fn publish(queue_addr: &str, body: &[u8], properties: BasicProperties) -> Result<(), PublishError> {
+    let result = try_publish(queue_addr, body, properties).await;
+    match &result {
+        Err(PublishError::Unroutable(reason)) => {
+            metrics().inc("wms_messages_unroutable_total", &[("queue", queue_addr)]);
+            warn!("rabbitmq", "Publish to {} returned by broker: {}", queue_addr, reason);
+        }
+        // A confirm that never came may mean the channel is stuck, so start over on a new one
+        Err(PublishError::AsyncBroker(_) | PublishError::Unconfirmed) => publisher().reset().await,
+        _ => {}
+    }
+    result
+}
+
+async fn try_publish(queue_addr: &str, body: &[u8], properties: BasicProperties) -> Result<(), PublishError> {
+    let channel = publisher().channel().await?;
+    let options = BasicPublishOptions { mandatory: true, ..BasicPublishOptions::default() };
+    let confirm = channel.basic_publish("", queue_addr, options, body, properties.with_delivery_mode(2)).await?;
+    let confirmation = tokio::time::timeout(CONFIRM_TIMEOUT, confirm).await.map_err(|_| PublishError::Unconfirmed)?;
+    match confirmation? {
+        Confirmation::Ack(Some(returned)) => Err(PublishError::Unroutable(format!("{} {} (routing key {})",
+                returned.reply_code, returned.reply_text, returned.delivery.routing_key))),
+        Confirmation::Ack(None) | Confirmation::NotRequested => Ok(()),
+        Confirmation::Nack(_) => Err(PublishError::Nacked),
+    }
+}
+
+// A received message; settle it with ack, nack or dead_letter. Unsettled messages are redelivered
+// when the subscription's connection closes.
+pub struct AsyncMessage {
+    pub body: Vec<u8>,
+    pub content_type: Option<String>,
+    pub redelivered: bool,
+    queue_name: String,
+    delivery: lapin::message::Delivery,
+}
+
+impl AsyncMessage {
+    pub fn decode<T: DeserializeOwned>(&self) -> Result<T, String> {
+        Codec::from_content_type(self.content_type.as_deref())?.decode(&self.body)
+    }
+
+    pub async fn ack(&self) -> lapin::Result<()> {
+        self.delivery.acker.ack(BasicAckOptions::default()).await
+    }
+
+    pub async fn nack(&self, requeue: bool) -> lapin::Result<()> {
+        self.delivery.acker.nack(BasicNackOptions { requeue, ..BasicNackOptions::default() }).await
+    }
+
+    // Move to <queue>.dlq with the reason in its headers, only acknowledging once that is confirmed
+    pub async fn dead_letter(&self, reason: &str) {
+        let dlq = dead_letter_queue(&self.queue_name);
+        let mut headers = FieldTable::default();
+        headers.insert("x-original-queue".into(), AMQPValue::LongString(self.queue_name.as_str().into()));
+        headers.insert("x-error-reason".into(), AMQPValue::LongString(reason.into()));
+        headers.insert("x-failed-at-us".into(), AMQPValue::LongLongInt(now_micros() as i64));
+        let mut properties = BasicProperties::default().with_headers(headers);
+        if let Some(content_type) = &self.content_type {
+            properties = properties.with_content_type(content_type.as_str().into());
+        }
+        let outcome = match declare_durable(&dlq).await.map_err(PublishError::from) {
+            Ok(()) => publish(&dlq, &self.body, properties).await,
+            Err(e) => Err(e),
+        };
+        let settled = match outcome {
+            Ok(()) => {
+                metrics().inc("wms_messages_dead_lettered_total", &[("queue", &self.queue_name)]);
+                warn!("rabbitmq", "Dead-lettered message from {}: {}", self.queue_name, reason);
+                self.ack().await
+            }
+            Err(e) => {
+                error!("rabbitmq", "Failed to dead-letter message from {} ({}): {}", self.queue_name, reason, e);
+                self.nack(true).await
+            }
+        };
+        if let Err(e) = settled {
+            error!("rabbitmq", "Failed to settle dead-lettered message: {}", e);
+        }
+    }
+
+    pub async fn settle(&self, settle: Settle) {
+        let result = match settle {
+            Settle::Ack => self.ack().await,
+            Settle::Requeue => self.nack(true).await,
+            Settle::DeadLetter(reason) => {
+                self.dead_letter(&reason).await;
+                Ok(())
+            }
+        };
+        if let Err(e) = result {
+            error!("rabbitmq", "Failed to settle message from {}: {}", self.queue_name, e);
+        }
+    }
+}
+
+async fn declare_durable(queue_name: &str) -> lapin::Result<()> {
+    let channel = publisher().channel().await?;
+    let options = QueueDeclareOptions { durable: true, ..QueueDeclareOptions::default() };
+    channel.queue_declare(queue_name, options, FieldTable::default()).await.map(|_| ())
+}
+
+// A consumer on one queue that reconnects with backoff whenever the broker goes away
+pub struct Subscription {
+    queue_name: String,
+    prefetch: u16,
+    consumer: Option<(Connection, Consumer)>,
+    failures: u32,
+}
+
+impl Subscription {
+    // At most `prefetch` messages are delivered ahead of being settled
+    pub fn new(queue_name: &str, prefetch: u16) -> Self {
+        Subscription { queue_name: queue_name.to_string(), prefetch, consumer: None, failures: 0 }
+    }
+
+    pub async fn next(&mut self) -> AsyncMessage {
+        loop {
+            if self.consumer.is_none() {
+                match self.subscribe().await {
+                    Ok(consumer) => self.consumer = Some(consumer),
+                    Err(e) => {
+                        metrics().inc("wms_message_consume_failures_total", &[("queue", &self.queue_name)]);
+                        error!("rabbitmq", "Subscribe to {} failed: {}", self.queue_name, e);
+                        self.failures += 1;
+                        tokio::time::sleep(retry_policy().backoff(self.failures)).await;
+                        continue;
+                    }
+                }
+            }
+            let Some((_, consumer)) = self.consumer.as_mut() else { continue };
+            match consumer.next().await {
+                Some(Ok(delivery)) => {
+                    self.failures = 0;
+                    return AsyncMessage {
+                        body: delivery.data.clone(),
+                        content_type: delivery.properties.content_type().as_ref().map(|c| c.to_string()),
+                        redelivered: delivery.redelivered,
+                        queue_name: self.queue_name.clone(),
+                        delivery,
+                    };
+                }
+                Some(Err(e)) => {
+                    metrics().inc("wms_message_consume_failures_total", &[("queue", &self.queue_name)]);
+                    error!("rabbitmq", "Receive from {} failed: {}", self.queue_name, e);
+                    self.consumer = None;
+                }
+                None => {
+                    warn!("rabbitmq", "Consumer on {} ended", self.queue_name);
+                    self.consumer = None;
+                }
+            }
+        }
+    }
+
+    async fn subscribe(&self) -> lapin::Result<(Connection, Consumer)> {
+        let connection = connect().await?;
+        let channel = connection.create_channel().await?;
+        channel.basic_qos(self.prefetch, BasicQosOptions::default()).await?;
+        // Declared with its arguments by the topology at startup
+        let passive = QueueDeclareOptions { passive: true, ..QueueDeclareOptions::default() };
+        channel.queue_declare(&self.queue_name, passive, FieldTable::default()).await?;
+        let tag = format!("{}-{}", self.queue_name, Ulid::new());
+        let consumer = channel.basic_consume(&self.queue_name, &tag, BasicConsumeOptions::default(), FieldTable::default()).await?;
+        Ok((connection, consumer))
+    }
+}
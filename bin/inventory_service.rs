@@ -0,0 +1,6 @@
+// Inventory service: applies delivered orders, stores them in racks and answers stock requests
+use wms::services::{self, Service};
+
+fn main() {
+    services::main(Service::InventoryService);
+}
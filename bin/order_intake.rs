@@ -0,0 +1,6 @@
+// Order intake service: generates and accepts orders, reserving stock over the broker
+use wms::services::{self, Service};
+
+fn main() {
+    services::main(Service::OrderIntake);
+}
@@ -0,0 +1,6 @@
+// Reporter: prints the periodic inventory report from snapshots of the inventory service
+use wms::services::{self, Service};
+
+fn main() {
+    services::main(Service::Reporter);
+}
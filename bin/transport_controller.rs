@@ -0,0 +1,6 @@
+// Transport controller: assigns forklifts to orders from order_queue and hands them to transport_queue
+use wms::services::{self, Service};
+
+fn main() {
+    services::main(Service::TransportController);
+}
@@ -0,0 +1,374 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+use serde::{Serialize, Deserialize};
+
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+// Largest eaches per case or cases per pallet a catalog may give
+pub const MAX_PACK_FACTOR: u32 = 100_000;
+
+// Longest shelf life a catalog may give, a century
+pub const MAX_SHELF_LIFE_DAYS: u32 = 36_500;
+
+const MICROS_PER_DAY: u64 = 86_400_000_000;
+
+// Units an order quantity can be given in. Stock is always counted in eaches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Uom {
+    #[default]
+    Each,
+    Case,
+    Pallet,
+}
+
+// How many eaches make a case and how many cases make a pallet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PackHierarchy {
+    pub each_per_case: u32,
+    pub case_per_pallet: u32,
+}
+
+impl Default for PackHierarchy {
+    fn default() -> Self {
+        PackHierarchy { each_per_case: 1, case_per_pallet: 1 }
+    }
+}
+
+impl PackHierarchy {
+    // None if a pallet holds more eaches than a u32 counts
+    pub fn eaches(&self, uom: Uom) -> Option<u32> {
+        match uom {
+            Uom::Each => Some(1),
+            Uom::Case => Some(self.each_per_case),
+            Uom::Pallet => self.each_per_case.checked_mul(self.case_per_pallet),
+        }
+    }
+}
+
+// Outer dimensions of one each, in millimetres
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Dimensions {
+    pub length_mm: u32,
+    pub width_mm: u32,
+    pub height_mm: u32,
+}
+
+impl Dimensions {
+    pub fn volume_m3(&self) -> f64 {
+        self.length_mm as f64 * self.width_mm as f64 * self.height_mm as f64 / 1e9
+    }
+
+    // Whether something this size goes through an opening of `space`, turned whichever way fits
+    pub fn fits(&self, space: &Dimensions) -> bool {
+        let mut item = [self.length_mm, self.width_mm, self.height_mm];
+        let mut space = [space.length_mm, space.width_mm, space.height_mm];
+        item.sort_unstable();
+        space.sort_unstable();
+        item.iter().zip(space.iter()).all(|(item, space)| item <= space)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Temperature {
+    #[default]
+    Ambient,
+    Chilled,
+    Frozen,
+}
+
+// Where an item may be stored and how it has to be handled
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageRequirements {
+    pub hazardous: bool,
+    pub fragile: bool,
+    pub temperature: Temperature,
+}
+
+// Master data for one SKU; dimensions and weight are per each
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CatalogItem {
+    pub sku: String,
+    pub description: String,
+    #[serde(default)]
+    pub category: String,
+    #[serde(default)]
+    pub packs: PackHierarchy,
+    #[serde(default)]
+    pub dimensions: Dimensions,
+    #[serde(default)]
+    pub weight_kg: f64,
+    #[serde(default)]
+    pub storage: StorageRequirements,
+    #[serde(default)]
+    pub shelf_life_days: Option<u32>,   // From receipt; None for goods that do not expire
+}
+
+impl CatalogItem {
+    pub fn weight_kg(&self, eaches: i32) -> f64 {
+        self.weight_kg * eaches as f64
+    }
+
+    pub fn volume_m3(&self, eaches: i32) -> f64 {
+        self.dimensions.volume_m3() * eaches as f64
+    }
+
+    // When stock received at the given time expires; never later than u64::MAX, whatever the shelf life
+    pub fn expires_at_us(&self, received_at_us: u64) -> Option<u64> {
+        self.shelf_life_days.map(|days| {
+            (days as u64).checked_mul(MICROS_PER_DAY).map_or(u64::MAX, |shelf_life_us| received_at_us.saturating_add(shelf_life_us))
+        })
+    }
+}
+
+// A catalog file row: the CSV header names these columns, nested fields flattened. Blank cells take
+// the defaults, so the optional columns are all Options.
+#[derive(Debug, Deserialize)]
+struct CatalogRow {
+    sku: String,
+    description: String,
+    #[serde(default)]
+    category: String,
+    each_per_case: Option<u32>,
+    case_per_pallet: Option<u32>,
+    length_mm: Option<u32>,
+    width_mm: Option<u32>,
+    height_mm: Option<u32>,
+    weight_kg: Option<f64>,
+    hazardous: Option<bool>,
+    fragile: Option<bool>,
+    temperature: Option<Temperature>,
+    shelf_life_days: Option<u32>,
+}
+
+impl From<CatalogRow> for CatalogItem {
+    fn from(row: CatalogRow) -> Self {
+        CatalogItem {
+            sku: row.sku,
+            description: row.description,
+            category: row.category,
+            packs: PackHierarchy { each_per_case: row.each_per_case.unwrap_or(1), case_per_pallet: row.case_per_pallet.unwrap_or(1) },
+            dimensions: Dimensions {
+                length_mm: row.length_mm.unwrap_or_default(),
+                width_mm: row.width_mm.unwrap_or_default(),
+                height_mm: row.height_mm.unwrap_or_default(),
+            },
+            weight_kg: row.weight_kg.unwrap_or_default(),
+            storage: StorageRequirements {
+                hazardous: row.hazardous.unwrap_or_default(),
+                fragile: row.fragile.unwrap_or_default(),
+                temperature: row.temperature.unwrap_or_default(),
+            },
+            shelf_life_days: row.shelf_life_days,
+        }
+    }
+}
+
+// Item master data by SKU
+#[derive(Debug, Clone)]
+pub struct Catalog {
+    items: BTreeMap<String, CatalogItem>,
+}
+
+impl Default for Catalog {
+    // The demo furniture used when no catalog file is configured
+    fn default() -> Self {
+        let item = |sku: &str, description: &str, dimensions: [u32; 3], weight_kg: f64, fragile: bool| CatalogItem {
+            sku: sku.to_string(),
+            description: description.to_string(),
+            category: "furniture".to_string(),
+            packs: PackHierarchy { each_per_case: 1, case_per_pallet: 10 },
+            dimensions: Dimensions { length_mm: dimensions[0], width_mm: dimensions[1], height_mm: dimensions[2] },
+            weight_kg,
+            storage: StorageRequirements { fragile, ..StorageRequirements::default() },
+            shelf_life_days: None,
+        };
+        Catalog::new(vec![
+            item("001", "Table", [1200, 800, 120], 9.5, false),
+            item("002", "Chair", [600, 550, 450], 3.2, false),
+            item("003", "Cupboard", [900, 500, 300], 14.0, true),
+        ])
+    }
+}
+
+impl Catalog {
+    pub fn new(items: Vec<CatalogItem>) -> Self {
+        Catalog { items: items.into_iter().map(|item| (item.sku.clone(), item)).collect() }
+    }
+
+    // A .csv file with one row per SKU, or a JSON array of items
+    pub fn load(path: &str) -> Result<Self, String> {
+        let items = if Path::new(path).extension().is_some_and(|ext| ext.eq_ignore_ascii_case("csv")) {
+            let mut reader = csv::Reader::from_path(path).map_err(|e| format!("Failed to read catalog {}: {}", path, e))?;
+            let rows: Result<Vec<CatalogRow>, _> = reader.deserialize().collect();
+            rows.map_err(|e| format!("Invalid catalog {}: {}", path, e))?.into_iter().map(CatalogItem::from).collect()
+        } else {
+            let text = fs::read_to_string(path).map_err(|e| format!("Failed to read catalog {}: {}", path, e))?;
+            serde_json::from_str::<Vec<CatalogItem>>(&text).map_err(|e| format!("Invalid catalog {}: {}", path, e))?
+        };
+        let mut seen = BTreeSet::new();
+        let duplicates: BTreeSet<&str> = items.iter().map(|item| item.sku.as_str()).filter(|sku| !seen.insert(*sku)).collect();
+        if !duplicates.is_empty() {
+            let duplicates: Vec<&str> = duplicates.into_iter().collect();
+            return Err(format!("Invalid catalog {}: duplicate sku {}", path, duplicates.join(", ")));
+        }
+        let catalog = Catalog::new(items);
+        let errors = catalog.validate();
+        if !errors.is_empty() {
+            return Err(format!("Invalid catalog {}:\n{}", path, errors.join("\n")));
+        }
+        Ok(catalog)
+    }
+
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.items.is_empty() {
+            errors.push("the catalog has no items".to_string());
+        }
+        for item in self.items.values() {
+            if item.sku.trim().is_empty() {
+                errors.push("sku must not be empty".to_string());
+            }
+            let factors = [item.packs.each_per_case, item.packs.case_per_pallet];
+            if factors.iter().any(|&factor| factor == 0 || factor > MAX_PACK_FACTOR) {
+                errors.push(format!("{}: pack conversion factors must be between 1 and {}", item.sku, MAX_PACK_FACTOR));
+            } else if item.packs.eaches(Uom::Pallet).and_then(|eaches| i32::try_from(eaches).ok()).is_none() {
+                errors.push(format!("{}: a pallet must hold at most {} eaches", item.sku, i32::MAX));
+            }
+            let dimensions = &item.dimensions;
+            if [dimensions.length_mm, dimensions.width_mm, dimensions.height_mm].contains(&0) {
+                errors.push(format!("{}: length_mm, width_mm and height_mm must be given and greater than 0", item.sku));
+            }
+            if item.weight_kg < 0.0 {
+                errors.push(format!("{}: weight_kg must not be negative", item.sku));
+            }
+            if item.shelf_life_days.is_some_and(|days| days == 0 || days > MAX_SHELF_LIFE_DAYS) {
+                errors.push(format!("{}: shelf_life_days must be between 1 and {}", item.sku, MAX_SHELF_LIFE_DAYS));
+            }
+        }
+        errors
+    }
+
+    pub fn get(&self, sku: &str) -> Option<&CatalogItem> {
+        self.items.get(sku)
+    }
+
+    pub fn items(&self) -> impl Iterator<Item = &CatalogItem> {
+        self.items.values()
+    }
+
+    // A quantity in the given unit as eaches, for SKUs in the catalog
+    pub fn to_eaches(&self, sku: &str, quantity: i32, uom: Uom) -> Result<i32, String> {
+        let item = self.get(sku).ok_or_else(|| format!("Item not in catalog: {}", sku))?;
+        item.packs.eaches(uom).and_then(|eaches| i32::try_from(eaches).ok())
+            .and_then(|eaches| quantity.checked_mul(eaches))
+            .ok_or_else(|| format!("Quantity too large: {} {:?}", quantity, uom))
+    }
+}
+
+// Set once at startup from the config, before anything looks an item up; the demo catalog otherwise
+pub fn configure(catalog: Catalog) -> Result<(), String> {
+    CATALOG.set(catalog).map_err(|_| "Catalog configured after it was first used".to_string())
+}
+
+pub fn catalog() -> &'static Catalog {
+    CATALOG.get_or_init(Catalog::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CSV_HEADER: &str = "sku,description,category,each_per_case,case_per_pallet,length_mm,width_mm,height_mm,weight_kg,hazardous,fragile,temperature,shelf_life_days";
+
+    // Load the contents from a file of the given name in the temp directory
+    fn load(name: &str, contents: &str) -> Result<Catalog, String> {
+        let path = std::env::temp_dir().join(format!("wms-catalog-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        let catalog = Catalog::load(path.to_str().unwrap());
+        let _ = fs::remove_file(&path);
+        catalog
+    }
+
+    fn item(sku: &str) -> CatalogItem {
+        CatalogItem {
+            sku: sku.to_string(),
+            description: "Box".to_string(),
+            category: String::new(),
+            packs: PackHierarchy::default(),
+            dimensions: Dimensions { length_mm: 500, width_mm: 500, height_mm: 500 },
+            weight_kg: 10.0,
+            storage: StorageRequirements::default(),
+            shelf_life_days: None,
+        }
+    }
+
+    #[test]
+    fn csv_rows_fill_nested_fields_and_defaults() {
+        let csv = format!("{}\n001,Milk,food,6,40,100,100,250,1.1,false,false,chilled,7\n002,Bolt,,,,10,10,50,0.1,,,,\n", CSV_HEADER);
+        let catalog = load("rows.csv", &csv).unwrap();
+        let milk = catalog.get("001").unwrap();
+        assert_eq!((milk.packs.each_per_case, milk.packs.case_per_pallet), (6, 40));
+        assert_eq!(milk.storage.temperature, Temperature::Chilled);
+        assert_eq!(milk.shelf_life_days, Some(7));
+        let bolt = catalog.get("002").unwrap();
+        assert_eq!((bolt.packs.each_per_case, bolt.packs.case_per_pallet), (1, 1));
+        assert_eq!(bolt.storage.temperature, Temperature::Ambient);
+        assert_eq!(bolt.shelf_life_days, None);
+    }
+
+    #[test]
+    fn json_items_are_read_and_unknown_fields_refused() {
+        let json = r#"[{"sku": "001", "description": "Table", "dimensions": {"length_mm": 1200, "width_mm": 800, "height_mm": 120}, "weight_kg": 9.5}]"#;
+        let catalog = load("items.json", json).unwrap();
+        assert_eq!(catalog.get("001").unwrap().dimensions.length_mm, 1200);
+        let unknown = r#"[{"sku": "001", "description": "Table", "colour": "oak"}]"#;
+        assert!(load("unknown.json", unknown).unwrap_err().contains("unknown field"));
+    }
+
+    #[test]
+    fn duplicate_skus_are_refused() {
+        let csv = format!("{}\n001,Table,,,,1,1,1,1,,,,\n001,Chair,,,,1,1,1,1,,,,\n", CSV_HEADER);
+        assert!(load("duplicates.csv", &csv).unwrap_err().contains("duplicate sku 001"));
+    }
+
+    #[test]
+    fn pack_factors_must_fit_a_pallet_in_i32() {
+        let mut too_large = item("001");
+        too_large.packs = PackHierarchy { each_per_case: MAX_PACK_FACTOR + 1, case_per_pallet: 1 };
+        let mut overflowing = item("002");
+        overflowing.packs = PackHierarchy { each_per_case: MAX_PACK_FACTOR, case_per_pallet: MAX_PACK_FACTOR };
+        let errors = Catalog::new(vec![too_large, overflowing]).validate();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].contains("between 1 and"));
+        assert!(errors[1].contains("at most"));
+    }
+
+    #[test]
+    fn missing_dimensions_are_refused() {
+        let mut flat = item("001");
+        flat.dimensions.height_mm = 0;
+        assert_eq!(Catalog::new(vec![flat]).validate().len(), 1);
+        let csv = format!("{}\n001,Table,,,,1200,800,,9.5,,,,\n", CSV_HEADER);
+        assert!(load("no-height.csv", &csv).unwrap_err().contains("height_mm"));
+    }
+
+    #[test]
+    fn shelf_life_is_bounded_and_expiry_saturates() {
+        let mut item = item("001");
+        item.shelf_life_days = Some(u32::MAX);
+        assert!(Catalog::new(vec![item.clone()]).validate()[0].contains("shelf_life_days"));
+        assert_eq!(item.expires_at_us(1), Some(u64::MAX));
+        item.shelf_life_days = Some(MAX_SHELF_LIFE_DAYS);
+        assert_eq!(item.expires_at_us(u64::MAX - 1), Some(u64::MAX));
+        item.shelf_life_days = Some(2);
+        assert_eq!(item.expires_at_us(5), Some(5 + 2 * MICROS_PER_DAY));
+    }
+}
@@ -0,0 +1,311 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use crate::config::Config;
+use crate::inventory_management::{Item, Rack};
+use crate::putaway;
+use crate::rabbitmq;
+
+#[derive(Debug, Parser)]
+#[command(name = "wms", about = "Real-time warehouse management system")]
+pub struct Cli {
+    /// Config file (defaults to wms.json when present)
+    #[arg(long, global = true)]
+    pub config: Option<String>,
+
+    /// Base URL of a running instance's API, used by the client commands
+    #[arg(long, global = true, default_value = "http://127.0.0.1:8080")]
+    pub api: String,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Start the warehouse systems (the default when no command is given)
+    Run(RunArgs),
+    /// Submit a supply or offload order to a running instance
+    SubmitOrder {
+        #[arg(long)]
+        code: String,
+        #[arg(long)]
+        quantity: i32,
+        #[arg(long = "type", value_parser = ["supply", "offload"])]
+        order_type: String,
+    },
+    /// Show item stock of a running instance
+    Stock {
+        /// Only this item, including its movement history
+        #[arg(long)]
+        code: Option<String>,
+    },
+    /// Show rack contents of a running instance
+    Racks,
+    /// Submit every order in a JSON lines file to a running instance
+    Replay {
+        file: String,
+    },
+    /// Print an inventory report of a running instance
+    Report {
+        #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+        format: ReportFormat,
+    },
+    /// Run the systems with a seeded order generator for a fixed time, then print a report
+    Simulate {
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Seconds to run
+        #[arg(long, default_value_t = 60)]
+        duration: u64,
+        #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+        format: ReportFormat,
+        /// Instead, run the same orders through every putaway strategy offline and compare them
+        #[arg(long)]
+        compare_putaway: bool,
+        /// Orders to simulate when comparing
+        #[arg(long, default_value_t = 1000)]
+        orders: usize,
+    },
+    /// Validate the config file and print the effective settings
+    CheckConfig,
+    /// Inspect or re-drive dead-lettered messages
+    Dlq {
+        #[command(subcommand)]
+        action: DlqCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum DlqCommand {
+    /// Show dead letters of a queue without removing them
+    List {
+        queue: String,
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+    },
+    /// Move dead letters back onto their original queue
+    Redrive {
+        queue: String,
+        #[arg(long, default_value_t = usize::MAX)]
+        limit: usize,
+    },
+}
+
+// Options of the single-service binaries in bin/
+#[derive(Debug, Parser)]
+pub struct ServiceArgs {
+    /// Config file (defaults to <service>.json, then wms.json, when present)
+    #[arg(long)]
+    pub config: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Args)]
+pub struct RunArgs {
+    /// Do not start the random order generator
+    #[arg(long)]
+    pub no_generator: bool,
+    /// Do not start the scheduled inventory report
+    #[arg(long)]
+    pub no_reporter: bool,
+    /// Do not start the goods transportation consumer
+    #[arg(long)]
+    pub no_transport: bool,
+    /// Show the terminal dashboard
+    #[arg(long)]
+    pub tui: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+// One line of a replay file, e.g. {"code": "001", "quantity": 120, "order_type": "offload", "delay_ms": 500}
+#[derive(Debug, Deserialize)]
+struct ReplayOrder {
+    code: String,
+    quantity: i32,
+    order_type: String,
+    #[serde(default)]
+    delay_ms: u64,
+}
+
+pub fn submit_order(api: &str, code: &str, quantity: i32, order_type: &str) -> Result<(), String> {
+    let body = json!({ "code": code, "quantity": quantity, "order_type": order_type });
+    let (status, response) = http(api, "POST", "/orders", Some(&body))?;
+    println!("{}", serde_json::to_string_pretty(&response).unwrap());
+    if status >= 300 {
+        return Err(format!("Order not accepted (HTTP {})", status));
+    }
+    Ok(())
+}
+
+pub fn stock(api: &str, code: Option<&str>) -> Result<(), String> {
+    match code {
+        Some(code) => {
+            let (status, response) = http(api, "GET", &format!("/items/{}", code), None)?;
+            if status != 200 {
+                return Err(format!("HTTP {}: {}", status, response));
+            }
+            println!("{}", serde_json::to_string_pretty(&response).unwrap());
+        }
+        None => {
+            let items: Vec<Item> = get(api, "/items")?;
+            println!("{:<6} {:<12} {:>8} {:>8} {:>8}", "Code", "Name", "Stock", "Entry", "Exit");
+            for item in items {
+                println!("{:<6} {:<12} {:>8} {:>8} {:>8}", item.code, item.name, item.quantity, item.entry, item.exit);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn racks(api: &str) -> Result<(), String> {
+    let racks: Vec<Rack> = get(api, "/racks")?;
+    println!("{:<10} {:>9} {:>15} {:>13} {:>6}", "Rack", "Boxes", "Weight kg", "Volume m3", "Fill");
+    for rack in racks {
+        println!("{:<10} {:>9} {:>15} {:>13} {:>5.0}%", rack.name, rack.capacity,
+            format!("{:.0}/{:.0}", rack.weight_kg(), rack.max_weight_kg()),
+            format!("{:.1}/{:.1}", rack.volume_m3(), rack.max_volume_m3()), rack.fill_ratio() * 100.0);
+    }
+    Ok(())
+}
+
+pub fn replay(api: &str, file: &str) -> Result<(), String> {
+    let contents = fs::read_to_string(file).map_err(|e| format!("Failed to read {}: {}", file, e))?;
+    let (mut accepted, mut rejected) = (0, 0);
+    for (line_no, line) in contents.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+        let order: ReplayOrder = serde_json::from_str(line).map_err(|e| format!("{}:{}: {}", file, line_no + 1, e))?;
+        thread::sleep(Duration::from_millis(order.delay_ms));
+        let body = json!({ "code": order.code, "quantity": order.quantity, "order_type": order.order_type });
+        let (status, response) = http(api, "POST", "/orders", Some(&body))?;
+        let state = response.get("status").and_then(Value::as_str).unwrap_or("error");
+        println!("line {}: {} {} x {} -> {}", line_no + 1, order.order_type, order.quantity, order.code, state);
+        if status < 300 { accepted += 1 } else { rejected += 1 }
+    }
+    println!("Replayed {} orders: {} accepted, {} rejected", accepted + rejected, accepted, rejected);
+    Ok(())
+}
+
+pub fn report(api: &str, format: ReportFormat) -> Result<(), String> {
+    let items: Vec<Item> = get(api, "/items")?;
+    let racks: Vec<Rack> = get(api, "/racks")?;
+    print!("{}", format_report(&items, &racks, format));
+    Ok(())
+}
+
+pub fn check_config(path: Option<&str>) -> Result<(), String> {
+    let config = Config::load(path)?;
+    println!("{}", serde_json::to_string_pretty(&config).unwrap());
+    let errors = config.validate();
+    if errors.is_empty() {
+        println!("Config OK");
+        Ok(())
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+pub fn dlq(action: DlqCommand) -> Result<(), String> {
+    match action {
+        DlqCommand::List { queue, limit } => {
+            let dead_letters = rabbitmq::inspect_dead_letters(&queue, limit).map_err(|e| e.to_string())?;
+            for dead_letter in &dead_letters {
+                println!("{}", serde_json::to_string(dead_letter).unwrap());
+            }
+            println!("{} dead letter(s) on {}", dead_letters.len(), rabbitmq::dead_letter_queue(&queue));
+        }
+        DlqCommand::Redrive { queue, limit } => {
+            let redriven = rabbitmq::redrive_dead_letters(&queue, limit).map_err(|e| e.to_string())?;
+            println!("Re-drove {} message(s) from {} to {}", redriven, rabbitmq::dead_letter_queue(&queue), queue);
+        }
+    }
+    Ok(())
+}
+
+pub fn compare_putaway(orders: usize, seed: u64, format: ReportFormat) {
+    let results = putaway::compare(orders, seed);
+    match format {
+        ReportFormat::Text => {
+            println!("{} orders, seed {}", orders, seed);
+            println!("{:<22} {:>7} {:>9} {:>9} {:>13} {:>11} {:>7} {:>7}",
+                "Strategy", "Stored", "Rejected", "Offloads", "Putaway m", "Pick m", "Fill", "Max");
+            for r in &results {
+                println!("{:<22} {:>7} {:>9} {:>9} {:>13.0} {:>11.0} {:>6.0}% {:>6.0}%", r.strategy, r.supplies_stored, r.supplies_rejected,
+                    r.offloads, r.putaway_travel_m, r.pick_travel_m, r.mean_fill_ratio * 100.0, r.max_fill_ratio * 100.0);
+            }
+        }
+        ReportFormat::Json => println!("{}", serde_json::to_string_pretty(&results).unwrap()),
+        ReportFormat::Csv => {
+            println!("strategy,supplies_stored,supplies_rejected,offloads,putaway_travel_m,pick_travel_m,mean_fill_ratio,max_fill_ratio");
+            for r in &results {
+                println!("{},{},{},{},{:.1},{:.1},{:.3},{:.3}", r.strategy, r.supplies_stored, r.supplies_rejected,
+                    r.offloads, r.putaway_travel_m, r.pick_travel_m, r.mean_fill_ratio, r.max_fill_ratio);
+            }
+        }
+    }
+}
+
+pub fn format_report(items: &[Item], racks: &[Rack], format: ReportFormat) -> String {
+    match format {
+        ReportFormat::Text => {
+            let mut out = String::new();
+            out.push_str("=========================== Inventory Report ============================\n");
+            for item in items {
+                out.push_str(&format!("{:?}\n", item));
+            }
+            out.push_str("============================== Rack Status ==============================\n");
+            for rack in racks {
+                out.push_str(&format!("{}\n", rack));
+            }
+            out.push_str("=========================================================================\n");
+            out
+        }
+        ReportFormat::Json => format!("{}\n", serde_json::to_string_pretty(&json!({ "items": items, "racks": racks })).unwrap()),
+        ReportFormat::Csv => {
+            let mut out = String::from("kind,id,name,quantity,entry,exit,fill_ratio\n");
+            for item in items {
+                out.push_str(&format!("item,{},{},{},{},{},\n", item.code, item.name, item.quantity, item.entry, item.exit));
+            }
+            for rack in racks {
+                out.push_str(&format!("rack,{},{},{},,,{:.3}\n", rack.name, rack.name, rack.capacity, rack.fill_ratio()));
+            }
+            out
+        }
+    }
+}
+
+fn get<T: for<'de> Deserialize<'de>>(api: &str, path: &str) -> Result<T, String> {
+    let (status, response) = http(api, "GET", path, None)?;
+    if status != 200 {
+        return Err(format!("HTTP {}: {}", status, response));
+    }
+    serde_json::from_value(response).map_err(|e| format!("Unexpected response from {}: {}", path, e))
+}
+
+// Minimal blocking HTTP/1.1 client for the local API
+fn http(api: &str, method: &str, path: &str, body: Option<&Value>) -> Result<(u16, Value), String> {
+    let host = api.trim_start_matches("http://").trim_end_matches('/');
+    let mut stream = TcpStream::connect(host).map_err(|e| format!("Cannot reach API at {}: {}", api, e))?;
+    let body = body.map(|b| b.to_string()).unwrap_or_default();
+    let request = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        method, path, host, body.len(), body
+    );
+    stream.write_all(request.as_bytes()).map_err(|e| e.to_string())?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).map_err(|e| e.to_string())?;
+
+    let (head, payload) = response.split_once("\r\n\r\n").ok_or("Malformed HTTP response")?;
+    let status = head.split_whitespace().nth(1).and_then(|s| s.parse().ok()).ok_or("Malformed HTTP status line")?;
+    let value = if payload.trim().is_empty() { Value::Null } else { serde_json::from_str(payload).map_err(|e| e.to_string())? };
+    Ok((status, value))
+}
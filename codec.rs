@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+
+static QUEUE_CODECS: OnceLock<BTreeMap<String, Codec>> = OnceLock::new();
+
+// Wire format of queued orders. Consumers pick the decoder from the message's content type,
+// so producers can switch codecs without stopping them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Codec {
+    #[default]
+    Json,
+    MessagePack,
+    Cbor,
+    Bincode,    // Compact, but field order must match on both sides
+}
+
+pub const CODECS: [Codec; 4] = [Codec::Json, Codec::MessagePack, Codec::Cbor, Codec::Bincode];
+
+// Set once at startup from the config, before anything is published: codec by queue name, JSON for the rest
+pub fn configure(queue_codecs: BTreeMap<String, Codec>) -> Result<(), String> {
+    QUEUE_CODECS.set(queue_codecs).map_err(|_| "Queue codecs configured after they were first used".to_string())
+}
+
+pub fn for_queue(queue_name: &str) -> Codec {
+    QUEUE_CODECS.get_or_init(BTreeMap::new).get(queue_name).copied().unwrap_or_default()
+}
+
+impl Codec {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Codec::Json => "application/json",
+            Codec::MessagePack => "application/msgpack",
+            Codec::Cbor => "application/cbor",
+            Codec::Bincode => "application/x-bincode",
+        }
+    }
+
+    // Messages without a content type predate codecs and are JSON
+    pub fn from_content_type(content_type: Option<&str>) -> Result<Codec, String> {
+        match content_type {
+            None => Ok(Codec::Json),
+            Some(content_type) => CODECS.into_iter().find(|c| c.content_type() == content_type)
+                .ok_or_else(|| format!("Unsupported content type: {}", content_type)),
+        }
+    }
+
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, String> {
+        match self {
+            Codec::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            Codec::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+            Codec::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes).map_err(|e| e.to_string())?;
+                Ok(bytes)
+            }
+            Codec::Bincode => bincode::serialize(value).map_err(|e| e.to_string()),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, String> {
+        match self {
+            Codec::Json => serde_json::from_slice(bytes).map_err(|e| e.to_string()),
+            Codec::MessagePack => rmp_serde::from_slice(bytes).map_err(|e| e.to_string()),
+            Codec::Cbor => ciborium::from_reader(bytes).map_err(|e| e.to_string()),
+            Codec::Bincode => bincode::deserialize(bytes).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::Uom;
+    use crate::latency::Timestamps;
+    use crate::order_processing::Order;
+    use crate::pick::Pick;
+
+    fn order() -> Order {
+        Order {
+            id: "01J9Z3K4M5N6P7Q8R9S0T1V2W3".to_string(),
+            index: 7,
+            code: "002".to_string(),
+            quantity: 12,
+            uom: Uom::Case,
+            order_type: "offload".to_string(),
+            timestamps: Timestamps::default(),
+            picks: vec![Pick { bin: "A-01-03".to_string(), quantity: 12, received_at_us: 1_000, expires_at_us: Some(2_000) }],
+        }
+    }
+
+    #[test]
+    fn every_codec_roundtrips_an_order() {
+        for codec in CODECS {
+            let bytes = codec.encode(&order()).unwrap();
+            let decoded: Order = codec.decode(&bytes).unwrap();
+            assert_eq!(serde_json::to_value(&decoded).unwrap(), serde_json::to_value(order()).unwrap(), "{:?}", codec);
+        }
+    }
+
+    #[test]
+    fn content_type_selects_the_codec() {
+        for codec in CODECS {
+            assert_eq!(Codec::from_content_type(Some(codec.content_type())), Ok(codec));
+        }
+        assert_eq!(Codec::from_content_type(None), Ok(Codec::Json));
+        assert!(Codec::from_content_type(Some("text/plain")).is_err());
+    }
+
+    #[test]
+    fn orders_from_before_units_and_picks_still_decode() {
+        let json = br#"{"id":"01J9Z3K4M5N6P7Q8R9S0T1V2W3","index":3,"code":"001","quantity":5,"order_type":"supply"}"#;
+        let decoded: Order = Codec::Json.decode(json).unwrap();
+        assert_eq!(decoded.uom, Uom::Each);
+        assert!(decoded.picks.is_empty());
+        assert_eq!(decoded.quantity, 5);
+    }
+}
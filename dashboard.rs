@@ -0,0 +1,134 @@
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::widgets::{Block, Gauge, List, ListItem, Paragraph, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+use crate::goods_transportation::GoodsTransportation;
+use crate::inventory_management::InventoryManagement;
+use crate::latency;
+use crate::metrics::metrics;
+use crate::order_registry::{registry, OrderStatus};
+
+const RECENT_ORDERS: usize = 20;
+
+// Full screen dashboard redrawn in place from the same state the scheduled report reads; q or Esc quits
+pub fn run(inventory_management: Arc<InventoryManagement>, goods_transportation: Arc<GoodsTransportation>) -> io::Result<()> {
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &inventory_management, &goods_transportation);
+    ratatui::restore();
+    result
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, inventory_management: &InventoryManagement, goods_transportation: &GoodsTransportation) -> io::Result<()> {
+    loop {
+        terminal.draw(|frame| draw(frame, inventory_management, goods_transportation))?;
+        if event::poll(Duration::from_millis(500))? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, inventory_management: &InventoryManagement, goods_transportation: &GoodsTransportation) {
+    let [top, middle, bottom, footer] = Layout::vertical([
+        Constraint::Percentage(30),
+        Constraint::Percentage(30),
+        Constraint::Fill(1),
+        Constraint::Length(1),
+    ]).areas(frame.area());
+    let [items_area, racks_area] = Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(top);
+    let [forklifts_area, queue_area] = Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(middle);
+    let [declined_area, latency_area] = Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(bottom);
+
+    draw_items(frame, items_area, inventory_management);
+    draw_racks(frame, racks_area, inventory_management);
+    draw_forklifts(frame, forklifts_area, goods_transportation);
+    draw_dispatch_queue(frame, queue_area);
+    draw_declined(frame, declined_area);
+    draw_latency(frame, latency_area);
+    frame.render_widget(Paragraph::new(" q: quit").dark_gray(), footer);
+}
+
+fn draw_items(frame: &mut Frame, area: Rect, inventory_management: &InventoryManagement) {
+    let rows = inventory_management.inventory_snapshot().into_iter().map(|item| {
+        Row::new(vec![item.code, item.name, item.quantity.to_string(), item.entry.to_string(), item.exit.to_string()])
+    });
+    let table = Table::new(rows, [Constraint::Length(6), Constraint::Fill(1), Constraint::Length(9), Constraint::Length(9), Constraint::Length(9)])
+        .header(Row::new(vec!["Code", "Name", "Stock", "Entry", "Exit"]).bold())
+        .block(Block::bordered().title(" Item Stock "));
+    frame.render_widget(table, area);
+}
+
+fn draw_racks(frame: &mut Frame, area: Rect, inventory_management: &InventoryManagement) {
+    let block = Block::bordered().title(" Rack Fill ");
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let racks = inventory_management.rack_snapshot();
+    let rows = Layout::vertical(racks.iter().map(|_| Constraint::Length(1))).split(inner);
+    for (rack, row) in racks.iter().zip(rows.iter()) {
+        let ratio = rack.fill_ratio();
+        let color = if ratio >= 0.9 { Color::Red } else if ratio >= 0.6 { Color::Yellow } else { Color::Green };
+        let gauge = Gauge::default()
+            .gauge_style(Style::default().fg(color))
+            .ratio(ratio.clamp(0.0, 1.0))
+            .label(format!("{} {} boxes, {:.0}%", rack.name, rack.capacity, ratio * 100.0));
+        frame.render_widget(gauge, *row);
+    }
+}
+
+fn draw_forklifts(frame: &mut Frame, area: Rect, goods_transportation: &GoodsTransportation) {
+    let rows = goods_transportation.forklift_status().into_iter().map(|forklift| {
+        let job = forklift.current_order.as_deref().and_then(|id| registry().get(id)).map(|record| {
+            format!("#{} {} {} x {}", record.order.index, record.order.order_type, record.order.quantity, record.order.code)
+        }).unwrap_or_default();
+        let state = if forklift.in_use { "busy".yellow() } else { "idle".green() };
+        Row::new(vec![forklift.name.into(), state, job.into()])
+    });
+    let table = Table::new(rows, [Constraint::Length(12), Constraint::Length(6), Constraint::Fill(1)])
+        .header(Row::new(vec!["Forklift", "State", "Current Job"]).bold())
+        .block(Block::bordered().title(" Forklifts "));
+    frame.render_widget(table, area);
+}
+
+fn draw_dispatch_queue(frame: &mut Frame, area: Rect) {
+    let depth = metrics().get("wms_dispatch_queue_depth", &[]);
+    let items: Vec<ListItem> = registry().recent(OrderStatus::Dispatched, RECENT_ORDERS).into_iter().map(|record| {
+        ListItem::new(format!("#{} {} {} x {}", record.order.index, record.order.order_type, record.order.quantity, record.order.code))
+    }).collect();
+    let list = List::new(items).block(Block::bordered().title(format!(" Dispatch Queue ({}) ", depth)));
+    frame.render_widget(list, area);
+}
+
+fn draw_declined(frame: &mut Frame, area: Rect) {
+    let items: Vec<ListItem> = registry().recent(OrderStatus::Declined, RECENT_ORDERS).into_iter().map(|record| {
+        ListItem::new(format!("#{} {} {} x {}", record.order.index, record.order.order_type, record.order.quantity, record.order.code)).red()
+    }).collect();
+    let list = List::new(items).block(Block::bordered().title(" Recent Declined Orders "));
+    frame.render_widget(list, area);
+}
+
+fn draw_latency(frame: &mut Frame, area: Rect) {
+    let rows = latency::recorder().summary().into_iter().map(|s| {
+        Row::new(vec![
+            s.segment,
+            s.order_type,
+            s.count.to_string(),
+            format!("{:.1}", s.p50_us as f64 / 1000.0),
+            format!("{:.1}", s.p95_us as f64 / 1000.0),
+            format!("{:.1}", s.p99_us as f64 / 1000.0),
+            format!("{:.1}", s.max_us as f64 / 1000.0),
+        ])
+    });
+    let widths = [Constraint::Length(11), Constraint::Length(8), Constraint::Length(6), Constraint::Length(9), Constraint::Length(9), Constraint::Length(9), Constraint::Length(9)];
+    let table = Table::new(rows, widths)
+        .header(Row::new(vec!["Stage", "Type", "Count", "p50 ms", "p95 ms", "p99 ms", "max ms"]).bold())
+        .block(Block::bordered().title(" Latency "));
+    frame.render_widget(table, area);
+}
@@ -0,0 +1,78 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use crate::config::DedupPolicy;
+use crate::metrics::metrics;
+
+static POLICY: OnceLock<DedupPolicy> = OnceLock::new();
+
+// Set once at startup from the config, before any deduplicator reads it
+pub fn configure(policy: DedupPolicy) -> Result<(), String> {
+    POLICY.set(policy).map_err(|_| "Dedup policy configured after it was first used".to_string())
+}
+
+pub fn policy() -> &'static DedupPolicy {
+    POLICY.get_or_init(DedupPolicy::default)
+}
+
+// Ids processed recently, oldest first, so expiry only ever looks at the front
+struct Seen {
+    ids: HashSet<String>,
+    order: VecDeque<(String, Instant)>,
+}
+
+// Remembers which order ids a stage has already applied, so a redelivered or duplicated message is a no-op.
+// Ids are forgotten after the retention window or once max_entries newer ids have been recorded.
+// They are kept in memory only, like the stock and racks they guard: a restarted service starts again
+// from the opening stock with no ids, and applies a message redelivered across the restart to that.
+// Persisting the stock would mean persisting the ids with it.
+pub struct Deduplicator {
+    stage: &'static str,
+    retention: Duration,
+    max_entries: usize,
+    seen: Mutex<Seen>,
+}
+
+impl Deduplicator {
+    pub fn new(stage: &'static str) -> Self {
+        let policy = policy();
+        Deduplicator {
+            stage,
+            retention: Duration::from_secs(policy.retention_secs),
+            max_entries: policy.max_entries,
+            seen: Mutex::new(Seen { ids: HashSet::new(), order: VecDeque::new() }),
+        }
+    }
+
+    // True when the id was already processed within the window; duplicates are counted and logged
+    pub fn is_duplicate(&self, id: &str) -> bool {
+        let mut seen = self.seen.lock().unwrap();
+        self.expire(&mut seen);
+        let duplicate = seen.ids.contains(id);
+        if duplicate {
+            metrics().inc("wms_duplicate_messages_total", &[("stage", self.stage)]);
+            warn!("dedup", order = id, "Duplicate order ignored by {}", self.stage);
+        }
+        duplicate
+    }
+
+    // Mark the id as processed; call only after the work it stands for has been applied
+    pub fn record(&self, id: &str) {
+        let mut seen = self.seen.lock().unwrap();
+        if seen.ids.insert(id.to_string()) {
+            seen.order.push_back((id.to_string(), Instant::now()));
+        }
+        self.expire(&mut seen);
+    }
+
+    fn expire(&self, seen: &mut Seen) {
+        while let Some((id, at)) = seen.order.front() {
+            if seen.order.len() <= self.max_entries && at.elapsed() < self.retention {
+                break;
+            }
+            let id = id.clone();
+            seen.ids.remove(&id);
+            seen.order.pop_front();
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use serde::{Serialize, Deserialize};
+use crate::latency::now_micros;
+use crate::metrics::metrics;
+use crate::order_registry::{registry, OrderStatus};
+use crate::rabbitmq::{follow_events, EventPublisher};
+
+// Events a subscriber can fall behind by; past that it misses events rather than buffer without limit
+const SUBSCRIBER_BUFFER: usize = 1024;
+
+// Warehouse events pushed to live subscribers (e.g. the /events stream)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    OrderCreated { order: String, code: String, quantity: i32, order_type: String },
+    OrderDeclined { order: String, code: String, quantity: i32, order_type: String },
+    ForkliftAssigned { order: String, forklift: String, code: String, quantity: i32, order_type: String },
+    ForkliftCompleted { order: String, forklift: String, code: String, quantity: i32, order_type: String },
+    InventoryUpdated { order: String, code: String, name: String, quantity: i32, entry: i32, exit: i32 },
+    RackChanged { order: String, rack: String, bin: String, code: String, change: i32, capacity: i32, fill_ratio: f64 },
+    ReportGenerated { items: usize, racks: usize },
+    OrderStatusChanged { order: String, code: String, quantity: i32, order_type: String, status: OrderStatus },
+}
+
+impl Event {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::OrderCreated { .. } => "order_created",
+            Event::OrderDeclined { .. } => "order_declined",
+            Event::ForkliftAssigned { .. } => "forklift_assigned",
+            Event::ForkliftCompleted { .. } => "forklift_completed",
+            Event::InventoryUpdated { .. } => "inventory_updated",
+            Event::RackChanged { .. } => "rack_changed",
+            Event::ReportGenerated { .. } => "report_generated",
+            Event::OrderStatusChanged { .. } => "order_status_changed",
+        }
+    }
+
+    // Topic routing key, e.g. order.offload.created or inventory.item.002.updated
+    pub fn routing_key(&self) -> String {
+        match self {
+            Event::OrderCreated { order_type, .. } => format!("order.{}.created", key_word(order_type)),
+            Event::OrderDeclined { order_type, .. } => format!("order.{}.declined", key_word(order_type)),
+            Event::ForkliftAssigned { forklift, .. } => format!("transport.forklift.{}.assigned", key_word(forklift)),
+            Event::ForkliftCompleted { forklift, .. } => format!("transport.forklift.{}.completed", key_word(forklift)),
+            Event::InventoryUpdated { code, .. } => format!("inventory.item.{}.updated", key_word(code)),
+            Event::RackChanged { rack, .. } => format!("storage.rack.{}.changed", key_word(rack)),
+            Event::ReportGenerated { .. } => "report.inventory.generated".to_string(),
+            Event::OrderStatusChanged { order_type, status, .. } => format!("order.{}.status.{}", key_word(order_type), status.name()),
+        }
+    }
+
+    pub fn item_code(&self) -> Option<&str> {
+        match self {
+            Event::OrderCreated { code, .. }
+            | Event::OrderDeclined { code, .. }
+            | Event::ForkliftAssigned { code, .. }
+            | Event::ForkliftCompleted { code, .. }
+            | Event::InventoryUpdated { code, .. }
+            | Event::RackChanged { code, .. }
+            | Event::OrderStatusChanged { code, .. } => Some(code),
+            Event::ReportGenerated { .. } => None,
+        }
+    }
+
+    pub fn forklift(&self) -> Option<&str> {
+        match self {
+            Event::ForkliftAssigned { forklift, .. } | Event::ForkliftCompleted { forklift, .. } => Some(forklift),
+            _ => None,
+        }
+    }
+
+    // JSON with the event time added alongside the tagged fields
+    pub fn to_json(&self, ts_us: u64) -> String {
+        let mut value = serde_json::to_value(self).unwrap();
+        value["ts_us"] = serde_json::Value::from(ts_us);
+        value.to_string()
+    }
+}
+
+// One routing key word: lowercase, with dots and spaces replaced so "Rack A" becomes rack_a
+fn key_word(value: &str) -> String {
+    value.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' { c.to_ascii_lowercase() } else { '_' }).collect()
+}
+
+// Subscriber side filters; empty lists match everything
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    pub kinds: Vec<String>,
+    pub item_codes: Vec<String>,
+    pub forklifts: Vec<String>,
+}
+
+impl EventFilter {
+    pub fn matches(&self, event: &Event) -> bool {
+        (self.kinds.is_empty() || self.kinds.iter().any(|k| k == event.kind()))
+            && (self.item_codes.is_empty() || event.item_code().map(|c| self.item_codes.iter().any(|f| f == c)).unwrap_or(false))
+            && (self.forklifts.is_empty() || event.forklift().map(|n| self.forklifts.iter().any(|f| f == n)).unwrap_or(false))
+    }
+}
+
+pub struct EventBus {
+    subscribers: Mutex<Vec<SyncSender<(u64, Event)>>>,
+}
+
+impl EventBus {
+    fn new() -> Self {
+        EventBus { subscribers: Mutex::new(Vec::new()) }
+    }
+
+    pub fn publish(&self, event: Event) {
+        let ts_us = now_micros();
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|tx| match tx.try_send((ts_us, event.clone())) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                metrics().inc("wms_events_dropped_total", &[("type", event.kind())]);
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,    // Drop subscribers that went away
+        });
+    }
+
+    pub fn subscribe(&self) -> Receiver<(u64, Event)> {
+        let (tx, rx) = sync_channel(SUBSCRIBER_BUFFER);
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+}
+
+// Republish every event to the topic exchange so other services can bind queues to the keys they need.
+// Best effort: events are not retried, the queues carrying orders are the source of truth.
+pub fn forward_to_broker(exchange: String) {
+    let events = bus().subscribe();
+    thread::spawn(move || {
+        let mut publisher = EventPublisher::new();
+        for (ts_us, event) in events {
+            if let Err(e) = publisher.publish(&exchange, &event.routing_key(), event.kind(), &event.to_json(ts_us)) {
+                metrics().inc("wms_message_publish_failures_total", &[("queue", &exchange)]);
+                warn!("events", "Failed to publish {} event: {}", event.kind(), e);
+            }
+        }
+    });
+}
+
+// Keep this service's order registry in step with the status changes the other services publish
+pub fn follow_order_status(exchange: String) {
+    thread::spawn(move || {
+        follow_events(&exchange, "order.*.status.*", |body| match serde_json::from_slice::<Event>(body) {
+            Ok(event) => registry().apply_remote(&event),
+            Err(e) => warn!("events", "Ignoring unreadable order status event: {}", e),
+        });
+    });
+}
+
+pub fn bus() -> &'static EventBus {
+    static BUS: OnceLock<EventBus> = OnceLock::new();
+    BUS.get_or_init(EventBus::new)
+}
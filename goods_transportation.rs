@@ -1,13 +1,31 @@
-use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
 use std::time::Duration;
 use rand::Rng;
-use crate::rabbitmq::{receive_message, send_message};
-use crate::order_processing::Order;
+use rand::rngs::StdRng;
+use scheduled_thread_pool::ScheduledThreadPool;
+use tokio::sync::Notify;
+use crate::catalog::catalog;
+use crate::config::TransportConfig;
+use crate::rabbitmq::{receive_message, retry_policy, send_message, Message, Settle};
+use crate::async_rabbitmq::{self, runtime, Subscription};
+use crate::order_processing::{seeded_rng, Order};
+use crate::pick::Pick;
+use crate::codec;
+use crate::latency::Stage;
+use crate::metrics::metrics;
+use crate::order_registry::{registry, OrderStatus};
+use crate::events::{bus, Event};
+use crate::outbox::outbox;
+use crate::trip_rpc::TripClaims;
+use serde::Serialize;
 
 struct Forklift {
     name: String,
     in_use: bool,
+    current_order: Option<String>,
+    picks: Vec<Pick>,   // Pick list of the current offload
 }
 
 impl Forklift {
@@ -15,118 +33,349 @@ impl Forklift {
         Forklift {
             name: name.to_string(),
             in_use: false,
+            current_order: None,
+            picks: Vec::new(),
         }
     }
 }
 
-pub struct GoodsTransportation {
+#[derive(Debug, Clone, Serialize)]
+pub struct ForkliftStatus {
+    pub name: String,
+    pub in_use: bool,
+    pub current_order: Option<String>,
+    pub picks: Vec<Pick>,
+}
+
+// The forklifts and where the round-robin search for a free one starts next
+struct Fleet {
     forklifts: Vec<Arc<Mutex<Forklift>>>,
+    next_forklift_index: Mutex<usize>,
+    freed: Condvar,     // Signalled with next_forklift_index when a forklift comes back
+    freed_async: Notify, // The same, for the async consumer
+    capacity_kg: f64,   // Per forklift load
+    rng: Mutex<StdRng>, // Trip times
+}
+
+pub struct GoodsTransportation {
+    fleet: Arc<Fleet>,
+    pool: Arc<ScheduledThreadPool>,     // Completes trips when their timers fire
+}
+
+impl Default for GoodsTransportation {
+    fn default() -> Self {
+        GoodsTransportation::new()
+    }
 }
 
 impl GoodsTransportation {
     pub fn new() -> Self {
-        let forklift_names = vec!["Forklift A", "Forklift B", "Forklift C"];
+        GoodsTransportation::with_config(&TransportConfig::default(), None)
+    }
+
+    // A seed makes trip times reproducible; they are drawn apart from the order stream seeded with the same value
+    pub fn with_config(config: &TransportConfig, seed: Option<u64>) -> Self {
         let mut forklifts = Vec::new();
-        for name in forklift_names {
-            forklifts.push(Arc::new(Mutex::new(Forklift::new(name))));
+        for index in 0..config.forklifts {
+            forklifts.push(Arc::new(Mutex::new(Forklift::new(&forklift_name(index)))));
         }
-        GoodsTransportation { forklifts }
+        info!("transport", "{} forklifts on {} transport worker threads", config.forklifts, config.workers);
+        metrics().set("wms_transport_workers", &[], config.workers as f64);
+        let pool = ScheduledThreadPool::builder().num_threads(config.workers).thread_name_pattern("transport-{}").build();
+        let fleet = Fleet {
+            forklifts,
+            next_forklift_index: Mutex::new(0),
+            freed: Condvar::new(),
+            freed_async: Notify::new(),
+            capacity_kg: config.forklift_capacity_kg,
+            rng: Mutex::new(seeded_rng(seed.map(|seed| seed.wrapping_add(1)))),
+        };
+        GoodsTransportation { fleet: Arc::new(fleet), pool: Arc::new(pool) }
+    }
+
+    pub fn forklift_status(&self) -> Vec<ForkliftStatus> {
+        self.fleet.forklifts.iter().map(|forklift| {
+            let f = forklift.lock().unwrap();
+            ForkliftStatus { name: f.name.clone(), in_use: f.in_use, current_order: f.current_order.clone(), picks: f.picks.clone() }
+        }).collect()
     }
 
-    pub fn start(&self, queue: String) {
-        let forklifts = self.forklifts.clone();
-        let mut next_forklift_index = Arc::new(Mutex::new(0));
+    // Consume orders from `queue` and publish delivered orders to `output_queue`. An order is acked only
+    // once it is published or queued in the outbox, so one lost mid-trip is redelivered.
+    // Each trip is first claimed from order intake, which owns cancellation.
+    pub fn start(&self, queue: String, output_queue: String, claims: Arc<dyn TripClaims>) {
+        let fleet = Arc::clone(&self.fleet);
+        let pool = Arc::clone(&self.pool);
+        let (delivered_tx, delivered_rx) = channel();
+        thread::spawn(move || publish_delivered(delivered_rx, output_queue));
         thread::spawn(move || {
             loop {
-                let order_message = receive_message(&queue); // Start listening to the queue and always ready for work
-                match serde_json::from_str::<Order>(&order_message) {
-                    Ok(d_order) => {
-                        let mut forklift_found = false;
-                        let forklifts_count = forklifts.len();
-
-                        for _ in 0..forklifts_count {   // Try to find an available forklift in a separate thread
-                            let forklift_index;
-                            {
-                                let mut index_guard = next_forklift_index.lock().unwrap();
-                                forklift_index = *index_guard;
-                                *index_guard = (*index_guard + 1) % forklifts_count;
-                            }
-                            let forklift = Arc::clone(&forklifts[forklift_index]);
-                            let forklift_available = {
-                                let mut f = forklift.lock().unwrap();
-                                if !f.in_use {
-                                    f.in_use = true;
-                                    let forklift_name = f.name.clone();
-                                    match d_order.order_type.as_str() {
-                                        "supply" => {
-                                            println!(
-                                                "...transporting Supply Order {}: {} is transporting {} boxes of {} from Receiving Area to Storage",
-                                                d_order.index, forklift_name, d_order.quantity, d_order.code
-                                            );
-                                        }
-                                        "offload" => {
-                                            println!(
-                                                "...transporting Offload Order {}: {} is transporting {} boxes of {} from Storage to Shipping Area",
-                                                d_order.index, forklift_name, d_order.quantity, d_order.code
-                                            );
-                                        }
-                                        _ => println!("Unknown order type: {:?}", d_order),
-                                    }
-                                    true
-                                } else {
-                                    false
-                                }
-                            };
-
-                            if forklift_available {
-                                let d_order_clone = d_order.clone();
-                                let forklift_name = {
-                                    let f = forklift.lock().unwrap();
-                                    f.name.clone()
-                                };
-                                
-                                thread::spawn(move || {     // New thread created to handle each Order individually
-                                    let mut rng = rand::thread_rng();       // Simulate transportation delay
-                                    thread::sleep(Duration::from_secs(rng.gen_range(5..8)));
-                                    println!();
-                                    match d_order_clone.order_type.as_str() {
-                                        "supply" => {
-                                            println!(
-                                                "(✅ Completed!) Supply Order {}: {} boxes of item {} has reached Storage using {}",
-                                                d_order_clone.index, d_order_clone.quantity, d_order_clone.code, forklift_name
-                                            );
-                                        }
-                                        "offload" => {
-                                            println!(
-                                                "(✅ Completed! ) Offload Order {}): {} boxes of item {} has reached Shipping Area using {}",
-                                                d_order_clone.index, d_order_clone.quantity, d_order_clone.code, forklift_name
-                                            );
-                                        }
-                                        _ => println!("Unknown order type: {:?}", d_order),
-                                    }
-                                    let s_order = serde_json::to_string(&d_order_clone).expect("Failed to serialize order");
-                                    if let Err(e) = send_message("transport_queue", &s_order) {
-                                        eprintln!("Failed to send message: {}", e);
-                                    }
-                                    let mut f = forklift.lock().unwrap();
-                                    f.in_use = false;
-                                });     // Thread will be destroyed and cleaned automatically by Rust runtime after finish
-
-                                forklift_found = true;
-                                next_forklift_index = Arc::new(Mutex::new((forklift_index + 1) % forklifts_count)); // Update the next forklift index
-                                break;
-                            } else {
-                                next_forklift_index = Arc::new(Mutex::new((forklift_index + 1) % forklifts_count)); // Update the next forklift index
-                            }
+                // Only take an order once a forklift can carry it, so while the fleet is busy orders wait on the queue
+                fleet.wait_for_free();
+                let message = receive_message(&queue); // Start listening to the queue and always ready for work
+                let d_order = match message.decode::<Order>() {
+                    Ok(d_order) => d_order,
+                    Err(e) => {
+                        metrics().inc("wms_message_consume_failures_total", &[("queue", &queue)]);
+                        error!("transport", "Failed to deserialize order: {}", e);
+                        message.dead_letter(&format!("Failed to deserialize order: {}", e));
+                        continue;
+                    }
+                };
+                match accept(claims.as_ref(), &d_order) {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        if let Err(e) = message.ack() {
+                            error!("transport", order = d_order.id, "Failed to ack message: {}", e);
+                        }
+                        continue;
+                    }
+                    Err(e) => {
+                        // Intake decides whether the order was cancelled, so it waits on the queue until intake answers
+                        warn!("transport", order = d_order.id, "Failed to claim trip, requeueing: {}", e);
+                        thread::sleep(retry_policy().backoff(1));
+                        if let Err(e) = message.nack(true) {
+                            error!("transport", order = d_order.id, "Failed to requeue message: {}", e);
                         }
-                        if !forklift_found {
-                            println!("No available forklift for order");
+                        continue;
+                    }
+                }
+
+                let Some(forklift_index) = fleet.assign(&d_order) else {
+                    // Only this thread assigns forklifts, so one was free; put the order back if not
+                    debug!("transport", order = d_order.id, "No available forklift, requeueing");
+                    if let Err(e) = message.nack(true) {
+                        error!("transport", order = d_order.id, "Failed to requeue message: {}", e);
+                    }
+                    continue;
+                };
+
+                let (mut d_order, forklift_name, trip) = fleet.start_trip(forklift_index, &d_order);
+                let fleet = Arc::clone(&fleet);
+                let delivered_tx = delivered_tx.clone();
+                // The trip finishes on a pool worker once its time is up; nothing waits on it meanwhile.
+                // Publishing may retry for a while, so it is left to the publisher thread.
+                pool.execute_after(trip, move || {
+                    finish_trip(&mut d_order, &forklift_name, trip);
+                    fleet.release(forklift_index);
+                    let _ = delivered_tx.send((d_order, message));
+                });
+            }
+        });
+    }
+
+    // As start, on the async runtime: each trip is a task waiting on a timer
+    pub fn start_async(&self, queue: String, output_queue: String, claims: Arc<dyn TripClaims>) {
+        let fleet = Arc::clone(&self.fleet);
+        let prefetch = self.fleet.forklifts.len().clamp(1, u16::MAX as usize) as u16;    // One order per forklift in hand at most
+        runtime().spawn(async move {
+            let mut subscription = Subscription::new(&queue, prefetch);
+            loop {
+                // As in start, take the next order only once a forklift is free to carry it
+                fleet.wait_for_free_async().await;
+                let message = subscription.next().await;
+                let d_order = match message.decode::<Order>() {
+                    Ok(d_order) => d_order,
+                    Err(e) => {
+                        metrics().inc("wms_message_consume_failures_total", &[("queue", &queue)]);
+                        error!("transport", "Failed to deserialize order: {}", e);
+                        message.dead_letter(&format!("Failed to deserialize order: {}", e)).await;
+                        continue;
+                    }
+                };
+                let claim = {
+                    let (claims, d_order) = (Arc::clone(&claims), d_order.clone());
+                    tokio::task::spawn_blocking(move || accept(claims.as_ref(), &d_order)).await
+                        .unwrap_or_else(|e| Err(e.to_string()))
+                };
+                match claim {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        message.settle(Settle::Ack).await;
+                        continue;
+                    }
+                    Err(e) => {
+                        warn!("transport", order = d_order.id, "Failed to claim trip, requeueing: {}", e);
+                        tokio::time::sleep(retry_policy().backoff(1)).await;
+                        message.settle(Settle::Requeue).await;
+                        continue;
+                    }
+                }
+
+                let Some(forklift_index) = fleet.assign(&d_order) else {
+                    // Only this loop assigns forklifts, so one was free; put the order back if not
+                    debug!("transport", order = d_order.id, "No available forklift, requeueing");
+                    message.settle(Settle::Requeue).await;
+                    continue;
+                };
+
+                let (mut d_order, forklift_name, trip) = fleet.start_trip(forklift_index, &d_order);
+                let fleet = Arc::clone(&fleet);
+                let output_queue = output_queue.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(trip).await;
+                    finish_trip(&mut d_order, &forklift_name, trip);
+                    let codec = codec::for_queue(&output_queue);
+                    let body = codec.encode(&d_order).expect("Failed to encode order");
+                    if let Err(e) = async_rabbitmq::send_message(&output_queue, &body, codec.content_type()).await {
+                        metrics().inc("wms_message_publish_failures_total", &[("queue", &output_queue)]);
+                        warn!("transport", order = d_order.id, "Failed to send message, queued in outbox: {}", e);
+                        outbox().push(&output_queue, &body, codec.content_type());
+                    }
+                    // The forklift is back before the ack lets the broker send the next order
+                    fleet.release(forklift_index);
+                    message.settle(Settle::Ack).await;
+                });
+            }
+        });
+    }
+}
+
+impl Fleet {
+    // Try each forklift once, starting after the last one assigned; the index of the one now taken
+    fn assign(&self, d_order: &Order) -> Option<usize> {
+        let forklifts_count = self.forklifts.len();
+        let mut next_forklift_index = self.next_forklift_index.lock().unwrap();
+        for _ in 0..forklifts_count {
+            let forklift_index = *next_forklift_index;
+            *next_forklift_index = (forklift_index + 1) % forklifts_count; // Update the next forklift index
+            let mut f = self.forklifts[forklift_index].lock().unwrap();
+            if !f.in_use {
+                f.in_use = true;
+                f.current_order = Some(d_order.id.clone());
+                f.picks = d_order.picks.clone();
+                match d_order.order_type.as_str() {
+                    "supply" => {
+                        info!("transport", order = d_order.id,
+                            "{} is transporting {} boxes of {} from Receiving Area to Storage",
+                            f.name, d_order.quantity, d_order.code
+                        );
+                    }
+                    "offload" => {
+                        info!("transport", order = d_order.id,
+                            "{} is transporting {} boxes of {} from Storage to Shipping Area",
+                            f.name, d_order.quantity, d_order.code
+                        );
+                        for pick in &d_order.picks {
+                            info!("transport", order = d_order.id, "{} picks {} from {}", f.name, pick.quantity, pick.bin);
                         }
                     }
-                    Err(e) => eprintln!("Failed to deserialize order: {}", e),
+                    _ => warn!("transport", order = d_order.id, "Unknown order type: {}", d_order.order_type),
                 }
+                return Some(forklift_index);
             }
+        }
+        None
+    }
+
+    // Mark the order in transit and pick the simulated trip time
+    fn start_trip(&self, forklift_index: usize, d_order: &Order) -> (Order, String, Duration) {
+        let forklift_name = self.forklifts[forklift_index].lock().unwrap().name.clone();
+        let mut d_order = d_order.clone();
+        d_order.mark(Stage::TransportStarted);
+        bus().publish(Event::ForkliftAssigned {
+            order: d_order.id.clone(), forklift: forklift_name.clone(), code: d_order.code.clone(),
+            quantity: d_order.quantity, order_type: d_order.order_type.clone(),
         });
+        metrics().inc("wms_transport_trips_in_progress", &[]);
+        // Orders heavier than a forklift can lift go in several loads, each a round trip
+        let weight_kg = catalog().get(&d_order.code).map(|item| item.weight_kg(d_order.quantity)).unwrap_or(0.0);
+        let loads = (weight_kg / self.capacity_kg).ceil().max(1.0) as u32;
+        if loads > 1 {
+            info!("transport", order = d_order.id, "{:.0} kg of {} takes {} loads for {}", weight_kg, d_order.code, loads, forklift_name);
+        }
+        let trip = Duration::from_secs(self.rng.lock().unwrap().gen_range(5..8));   // Simulate transportation delay
+        (d_order, forklift_name, trip * loads)
+    }
+
+    // Block until at least one forklift is free
+    fn wait_for_free(&self) {
+        let mut next_forklift_index = self.next_forklift_index.lock().unwrap();
+        while self.forklifts.iter().all(|forklift| forklift.lock().unwrap().in_use) {
+            next_forklift_index = self.freed.wait(next_forklift_index).unwrap();
+        }
+    }
+
+    async fn wait_for_free_async(&self) {
+        loop {
+            // Registered before the check, so a release in between still wakes it
+            let freed = self.freed_async.notified();
+            if self.forklifts.iter().any(|forklift| !forklift.lock().unwrap().in_use) {
+                return;
+            }
+            freed.await;
+        }
+    }
+
+    fn release(&self, forklift_index: usize) {
+        {
+            let mut f = self.forklifts[forklift_index].lock().unwrap();
+            f.in_use = false;
+            f.current_order = None;
+            f.picks.clear();
+        }
+        let _next_forklift_index = self.next_forklift_index.lock().unwrap();
+        self.freed.notify_all();
+        self.freed_async.notify_waiters();
+    }
+}
+
+// Whether a received order should be transported, claiming it for the trip; cancelled orders are acked and skipped
+fn accept(claims: &dyn TripClaims, d_order: &Order) -> Result<bool, String> {
+    if !claims.claim(d_order)? {
+        info!("transport", order = d_order.id, "Skipping cancelled order");
+        return Ok(false);
     }
+    Ok(true)
 }
 
+// Publish delivered orders in the order their trips finished, then ack what they were received as.
+// What the broker will not take goes to the outbox, which keeps it from then on.
+fn publish_delivered(delivered: Receiver<(Order, Message)>, output_queue: String) {
+    let codec = codec::for_queue(&output_queue);
+    for (d_order, message) in delivered {
+        let body = codec.encode(&d_order).expect("Failed to encode order");
+        if let Err(e) = send_message(&output_queue, &body, codec.content_type()) {
+            metrics().inc("wms_message_publish_failures_total", &[("queue", &output_queue)]);
+            warn!("transport", order = d_order.id, "Failed to send message, queued in outbox: {}", e);
+            outbox().push(&output_queue, &body, codec.content_type());
+        }
+        if let Err(e) = message.ack() {
+            error!("transport", order = d_order.id, "Failed to ack message: {}", e);
+        }
+    }
+}
+
+fn finish_trip(d_order: &mut Order, forklift_name: &str, trip: Duration) {
+    metrics().dec("wms_transport_trips_in_progress", &[]);
+    metrics().add("wms_forklift_busy_seconds_total", &[("forklift", forklift_name)], trip.as_secs_f64());
+    match d_order.order_type.as_str() {
+        "supply" => {
+            info!("transport", order = d_order.id,
+                "Completed: {} boxes of item {} has reached Storage using {}",
+                d_order.quantity, d_order.code, forklift_name
+            );
+        }
+        "offload" => {
+            info!("transport", order = d_order.id,
+                "Completed: {} boxes of item {} has reached Shipping Area using {}",
+                d_order.quantity, d_order.code, forklift_name
+            );
+        }
+        _ => warn!("transport", order = d_order.id, "Unknown order type: {}", d_order.order_type),
+    }
+    d_order.mark(Stage::TransportCompleted);
+    registry().set_status(d_order, OrderStatus::Delivered);
+    bus().publish(Event::ForkliftCompleted {
+        order: d_order.id.clone(), forklift: forklift_name.to_string(), code: d_order.code.clone(),
+        quantity: d_order.quantity, order_type: d_order.order_type.clone(),
+    });
+}
+
+// Forklift A to Forklift Z, then numbered
+fn forklift_name(index: usize) -> String {
+    match index {
+        0..=25 => format!("Forklift {}", (b'A' + index as u8) as char),
+        _ => format!("Forklift {}", index + 1),
+    }
+}
@@ -1,177 +1,752 @@
-use std::sync::{Arc, Mutex};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::thread;
-use crate::rabbitmq::receive_message;
+use crate::rabbitmq::{consume_batches, retry_policy, Settle};
+use crate::async_rabbitmq::{runtime, Subscription};
+use crate::catalog::{catalog, CatalogItem, Dimensions, StorageRequirements, Temperature};
+use crate::config::BatchConfig;
+use crate::inventory_tables::{ItemState, ItemTable, RackTable, Reservation};
+use crate::dedup::Deduplicator;
 use crate::order_processing::Order;
+use crate::pick;
+use crate::putaway::{self, FirstFit, PutawayError, PutawayKind, PutawayStrategy, Velocity};
+use crate::latency::{self, Stage, now_micros};
+use crate::order_registry::{registry, OrderStatus};
+use crate::events::{bus, Event};
+use crate::metrics::metrics;
+use crate::logging;
 use std::sync::mpsc::{Sender, Receiver};
-use std::time::Duration;    
+use std::time::{Duration, Instant};
 use scheduled_thread_pool::ScheduledThreadPool;
+use serde::{Serialize, Deserialize};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Item {
-    code: String,
-    name: String,
-    quantity: i32,
-    entry: i32,
-    exit: i32,
+    pub code: String,
+    pub name: String,
+    pub quantity: i32,
+    pub entry: i32,
+    pub exit: i32,
 }
 
-#[derive(Debug, Clone)]
-struct Rack {
-    name: String,
-    capacity: i32,
-    max_capacity: i32
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rack {
+    pub name: String,
+    pub capacity: i32,          // Boxes stored across all bins
+    #[serde(default)]
+    pub temperature: Temperature,
+    #[serde(default)]
+    pub hazardous: bool,        // Approved for hazardous goods
+    #[serde(default)]
+    pub receiving_m: f64,       // Walk from the receiving door to the rack's first bin
+    #[serde(default)]
+    pub shipping_m: f64,        // And from the shipping door
+    pub levels: Vec<Level>,     // From the floor up
 }
 
+// A bin by its indexes: rack, level from the floor, position along the rack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub rack: usize,
+    pub level: usize,
+    pub bin: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Level {
+    pub bins: Vec<Bin>,
+}
+
+// One storage location; what it holds is limited by its opening, its volume and its load rating
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bin {
+    pub name: String,
+    pub dimensions: Dimensions,
+    pub max_weight_kg: f64,
+    pub weight_kg: f64,
+    pub volume_m3: f64,
+    pub lots: Vec<Lot>,
+}
+
+// Stock of one SKU received into a bin at one time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lot {
+    pub sku: String,
+    pub quantity: i32,
+    pub received_at_us: u64,
+    pub expires_at_us: Option<u64>,
+}
+
+impl Rack {
+    // An ambient rack of empty, identical bins
+    pub fn new(name: &str, levels: usize, bins_per_level: usize, bin_dimensions: Dimensions, bin_max_weight_kg: f64) -> Self {
+        let levels = (1..=levels).map(|level| Level {
+            bins: (1..=bins_per_level).map(|position| Bin {
+                name: format!("{} L{} B{}", name, level, position),
+                dimensions: bin_dimensions.clone(),
+                max_weight_kg: bin_max_weight_kg,
+                weight_kg: 0.0,
+                volume_m3: 0.0,
+                lots: Vec::new(),
+            }).collect(),
+        }).collect();
+        Rack { name: name.to_string(), capacity: 0, temperature: Temperature::Ambient, hazardous: false, receiving_m: 0.0, shipping_m: 0.0, levels }
+    }
+
+    pub fn bin(&self, location: Location) -> &Bin {
+        &self.levels[location.level].bins[location.bin]
+    }
+
+    pub fn bin_mut(&mut self, location: Location) -> &mut Bin {
+        &mut self.levels[location.level].bins[location.bin]
+    }
+
+    // Metres from a door to a bin: to the rack, along it to the bin, then up to the bin's level
+    pub fn receiving_distance_m(&self, location: Location) -> f64 {
+        self.receiving_m + self.offset_m(location)
+    }
+
+    pub fn shipping_distance_m(&self, location: Location) -> f64 {
+        self.shipping_m + self.offset_m(location)
+    }
+
+    fn offset_m(&self, location: Location) -> f64 {
+        let dimensions = &self.bin(location).dimensions;
+        (location.bin as f64 * dimensions.length_mm as f64 + location.level as f64 * dimensions.height_mm as f64) / 1000.0
+    }
+
+    // Whether items with these requirements may be slotted here
+    pub fn accepts(&self, storage: &StorageRequirements) -> bool {
+        self.temperature == storage.temperature && (self.hazardous || !storage.hazardous)
+    }
+
+    pub fn bins(&self) -> impl Iterator<Item = &Bin> {
+        self.levels.iter().flat_map(|level| level.bins.iter())
+    }
+
+    pub fn weight_kg(&self) -> f64 {
+        self.bins().map(|bin| bin.weight_kg).sum()
+    }
+
+    pub fn max_weight_kg(&self) -> f64 {
+        self.bins().map(|bin| bin.max_weight_kg).sum()
+    }
+
+    pub fn volume_m3(&self) -> f64 {
+        self.bins().map(|bin| bin.volume_m3).sum()
+    }
+
+    pub fn max_volume_m3(&self) -> f64 {
+        self.bins().map(|bin| bin.dimensions.volume_m3()).sum()
+    }
+
+    // The fuller of weight and volume, as a fraction
+    pub fn fill_ratio(&self) -> f64 {
+        let weight = if self.max_weight_kg() > 0.0 { self.weight_kg() / self.max_weight_kg() } else { 0.0 };
+        let volume = if self.max_volume_m3() > 0.0 { self.volume_m3() / self.max_volume_m3() } else { 0.0 };
+        weight.max(volume)
+    }
+}
+
+impl fmt::Display for Rack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} boxes, {:.0}/{:.0} kg, {:.1}/{:.1} m3 ({:.0}% full)", self.name, self.capacity,
+            self.weight_kg(), self.max_weight_kg(), self.volume_m3(), self.max_volume_m3(), self.fill_ratio() * 100.0)
+    }
+}
+
+impl Bin {
+    // The fuller of weight and volume, as a fraction
+    pub fn fill_ratio(&self) -> f64 {
+        let weight = if self.max_weight_kg > 0.0 { self.weight_kg / self.max_weight_kg } else { 0.0 };
+        let volume = self.dimensions.volume_m3();
+        weight.max(if volume > 0.0 { self.volume_m3 / volume } else { 0.0 })
+    }
+
+    // How many more of the item fit: none if it does not fit the opening, else the lower of the weight and volume left
+    pub fn room_for(&self, item: &CatalogItem) -> i32 {
+        if !item.dimensions.fits(&self.dimensions) {
+            return 0;
+        }
+        let by_weight = if item.weight_kg > 0.0 { ((self.max_weight_kg - self.weight_kg) / item.weight_kg).floor() } else { f64::MAX };
+        let volume = item.dimensions.volume_m3();
+        let by_volume = if volume > 0.0 { ((self.dimensions.volume_m3() - self.volume_m3) / volume).floor() } else { f64::MAX };
+        by_weight.min(by_volume).clamp(0.0, i32::MAX as f64) as i32
+    }
+
+    pub fn quantity_of(&self, sku: &str) -> i32 {
+        self.lots.iter().filter(|lot| lot.sku == sku).map(|lot| lot.quantity).sum()
+    }
+
+    // Store a new lot; a lot of the same SKU received at the same time is topped up instead
+    pub fn add(&mut self, item: &CatalogItem, quantity: i32, received_at_us: u64) {
+        match self.lots.iter_mut().find(|lot| lot.sku == item.sku && lot.received_at_us == received_at_us) {
+            Some(lot) => lot.quantity += quantity,
+            None => self.lots.push(Lot {
+                sku: item.sku.clone(), quantity, received_at_us, expires_at_us: item.expires_at_us(received_at_us),
+            }),
+        }
+        self.weight_kg += item.weight_kg(quantity);
+        self.volume_m3 += item.volume_m3(quantity);
+    }
+
+    // Take up to quantity out of the lot of the item received at the given time, returning how many were taken
+    pub fn take(&mut self, item: &CatalogItem, received_at_us: u64, quantity: i32) -> i32 {
+        let Some(index) = self.lots.iter().position(|lot| lot.sku == item.sku && lot.received_at_us == received_at_us) else { return 0 };
+        let taken = quantity.min(self.lots[index].quantity);
+        self.lots[index].quantity -= taken;
+        if self.lots[index].quantity == 0 {
+            self.lots.remove(index);
+        }
+        self.weight_kg = (self.weight_kg - item.weight_kg(taken)).max(0.0);
+        self.volume_m3 = (self.volume_m3 - item.volume_m3(taken)).max(0.0);
+        taken
+    }
+}
+
+// One applied inventory movement
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryEntry {
+    pub order_id: String,
+    pub order_type: String,
+    pub quantity: i32,
+    pub balance: i32,
+    pub timestamp_us: u64,
+}
+
+// Why an order could not be applied: rejected orders can never succeed, unavailable ones can be retried
+// and cancelled ones are dropped
+#[derive(Debug)]
+pub enum UpdateError {
+    Rejected(String),
+    Unavailable(String),
+    Cancelled,
+}
+
+// How often each order came back unavailable, so it backs off and is dead-lettered after the last attempt
+#[derive(Default)]
+struct Retries {
+    attempts: Mutex<HashMap<String, u32>>,
+}
+
+impl Retries {
+    // The delay before the order is requeued, or None once it has used up its attempts
+    fn next(&self, id: &str) -> Option<Duration> {
+        let policy = retry_policy();
+        let mut attempts = self.attempts.lock().unwrap();
+        let attempt = attempts.entry(id.to_string()).or_insert(0);
+        *attempt += 1;
+        if *attempt >= policy.max_attempts {
+            attempts.remove(id);
+            return None;
+        }
+        Some(policy.backoff(*attempt))
+    }
+
+    fn clear(&self, id: &str) {
+        self.attempts.lock().unwrap().remove(id);
+    }
+}
+
+const OPENING_STOCK: i32 = 500;
+
 pub struct InventoryManagement {
-    inventory: Arc<Mutex<Vec<Item>>>,
-    racks: Arc<Mutex<Vec<Rack>>>,
+    inventory: Arc<ItemTable>,     // Stock, reservations and history, locked per item
+    racks: Arc<RackTable>,
+    applied_orders: Arc<Deduplicator>,
+    retries: Arc<Retries>,          // Of orders that could not be applied yet
+    stored_orders: Arc<Deduplicator>,
     pool: ScheduledThreadPool,
 }
 
+impl Default for InventoryManagement {
+    fn default() -> Self {
+        InventoryManagement::new()
+    }
+}
+
 impl InventoryManagement {
     pub fn new() -> Self {
-        let inventory = vec![
-            Item { code: "001".to_string(), name: "Table".to_string(), quantity: 500, entry: 0, exit: 0},
-            Item { code: "002".to_string(), name: "Chair".to_string(), quantity: 500, entry: 0, exit: 0},
-            Item { code: "003".to_string(), name: "Cupboard".to_string(), quantity: 500, entry: 0, exit: 0},
-        ];
-
-        let racks = vec![
-            Rack { name: "Rack A".to_string(), capacity: 1000, max_capacity: 1000},
-            Rack { name: "Rack B".to_string(), capacity: 500, max_capacity: 1000},
-            Rack { name: "Rack C".to_string(), capacity: 0, max_capacity: 1000},
-            Rack { name: "Rack D".to_string(), capacity: 0, max_capacity: 1000},
-            Rack { name: "Rack E".to_string(), capacity: 0, max_capacity: 1000},
-        ];
+        let inventory_management = InventoryManagement::with_stock(opening_stock(), demo_racks());
+        // Rack the opening stock so the racks hold what the inventory says
+        inventory_management.racks.with_racks(|racks| rack_opening_stock(racks, &FirstFit, &opening_stock(), now_micros()));
+        inventory_management
+    }
 
+    // Start from the given items and racks instead of the demo stock
+    pub fn with_stock(inventory: Vec<Item>, racks: Vec<Rack>) -> Self {
         InventoryManagement { 
-            inventory: Arc::new(Mutex::new(inventory)),
-            racks: Arc::new(Mutex::new(racks)),
+            inventory: Arc::new(ItemTable::new(inventory)),
+            racks: Arc::new(RackTable::new(racks)),
+            applied_orders: Arc::new(Deduplicator::new("inventory")),
+            retries: Arc::new(Retries::default()),
+            stored_orders: Arc::new(Deduplicator::new("storage")),
             pool: ScheduledThreadPool::new(1),
         }
     }
 
-    pub fn start(&self, queue: String, order_tx:Sender<Order>) {
+    pub fn start(&self, queue: String, order_tx:Sender<Order>, batch_config: BatchConfig) {
         let inventory_clone = Arc::clone(&self.inventory);
+        let applied_clone = Arc::clone(&self.applied_orders);
+        let retries = Arc::clone(&self.retries);
         thread::spawn(move || {
+            consume_batches(&queue, batch_config.size, batch_config.linger(), |batch| {
+                let decoded = (0..batch.len()).map(|index| batch.decode::<Order>(index)).collect();
+                let (outcomes, backoff) = InventoryManagement::process_batch(&inventory_clone, &applied_clone, &retries, &queue, decoded, &order_tx);
+                thread::sleep(backoff);
+                for (index, outcome) in outcomes.into_iter().enumerate() {
+                    batch.settle(index, outcome);
+                }
+            });
+        });
+    }
+
+    // As start, on the async runtime: the batch fills while the task waits, holding no thread
+    pub fn start_async(&self, queue: String, order_tx: Sender<Order>, batch_config: BatchConfig) {
+        let inventory_clone = Arc::clone(&self.inventory);
+        let applied_clone = Arc::clone(&self.applied_orders);
+        let retries = Arc::clone(&self.retries);
+        runtime().spawn(async move {
+            let mut subscription = Subscription::new(&queue, batch_config.size as u16);
             loop {
-                let order_message = receive_message(&queue);
-                match serde_json::from_str::<Order>(&order_message) {
-                    Ok(d_order) => {
-                        // Update inventory
-                        let mut inventory = inventory_clone.lock().unwrap();
-                        InventoryManagement::update_inventory(&mut inventory, &d_order, &order_tx);
+                let mut messages = vec![subscription.next().await];
+                let deadline = tokio::time::Instant::now() + batch_config.linger();
+                while messages.len() < batch_config.size {
+                    match tokio::time::timeout_at(deadline, subscription.next()).await {
+                        Ok(message) => messages.push(message),
+                        Err(_) => break,
                     }
-                    Err(e) => eprintln!("Failed to deserialize order: {}", e),
+                }
+                let decoded = messages.iter().map(|message| message.decode::<Order>()).collect();
+                let (outcomes, backoff) = InventoryManagement::process_batch(&inventory_clone, &applied_clone, &retries, &queue, decoded, &order_tx);
+                tokio::time::sleep(backoff).await;
+                for (message, outcome) in messages.iter().zip(outcomes) {
+                    message.settle(outcome).await;
                 }
             }
         });
     }
 
-    pub fn update_inventory(inventory: &mut Vec<Item>, order: &Order, order_tx: &Sender<Order>) {
-        if let Some(item) = inventory.iter_mut().find(|i| i.code == order.code) {
-            match order.order_type.as_str() {
-                "supply" => {
-                    item.entry += order.quantity;
-                    item.quantity += order.quantity;
+    // Apply a received batch and decide how each message is settled, in the order received. Orders are
+    // applied once however often they are delivered; retrying cannot fix undecodable or rejected ones.
+    // Also returns how long to wait before settling, so requeued orders are not redelivered at once.
+    fn process_batch(inventory: &ItemTable, applied: &Deduplicator, retries: &Retries, queue: &str, decoded: Vec<Result<Order, String>>,
+                        order_tx: &Sender<Order>) -> (Vec<Settle>, Duration) {
+        let mut outcomes = vec![Settle::Ack; decoded.len()];
+        let mut backoff = Duration::ZERO;
+        let mut indices = Vec::new();
+        let mut orders = Vec::new();
+        let mut batch_ids = HashSet::new();
+        for (index, d_order) in decoded.into_iter().enumerate() {
+            match d_order {
+                Ok(d_order) => {
+                    if applied.is_duplicate(&d_order.id) {
+                        // Applied before, so nothing is still on its way for the reservation
+                        inventory.with_item(&d_order.code, |state| state.reservations.remove(&d_order.id));
+                        continue;
+                    }
+                    if !batch_ids.insert(d_order.id.clone()) {
+                        continue;   // The copy earlier in this batch applies it and drops the reservation
+                    }
+                    indices.push(index);
+                    orders.push(d_order);
+                }
+                Err(e) => {
+                    metrics().inc("wms_message_consume_failures_total", &[("queue", queue)]);
+                    error!("inventory", "Failed to deserialize order: {}", e);
+                    outcomes[index] = Settle::DeadLetter(format!("Failed to deserialize order: {}", e));
+                }
+            }
+        }
+        if orders.is_empty() {
+            return (outcomes, backoff);
+        }
+
+        // Messages are settled by the caller once the item locks are released
+        let results = InventoryManagement::update_inventory(inventory, &orders, order_tx);
+        for ((index, d_order), result) in indices.into_iter().zip(&orders).zip(results) {
+            outcomes[index] = match result {
+                Ok(_) => {
+                    applied.record(&d_order.id);
+                    retries.clear(&d_order.id);
+                    Settle::Ack     // Only now is the order safe to remove from the queue
                 }
-                "offload" => {
-                    item.exit += order.quantity;
-                    item.quantity -= order.quantity;
+                Err(UpdateError::Rejected(reason)) => {
+                    retries.clear(&d_order.id);
+                    Settle::DeadLetter(reason)
                 }
-                _ => {
-                    println!("Unknown order type: {:?}", order);
-                    return;
+                Err(UpdateError::Cancelled) => {
+                    info!("inventory", order = d_order.id, "Skipping cancelled order");
+                    applied.record(&d_order.id);
+                    retries.clear(&d_order.id);
+                    Settle::Ack
                 }
+                Err(UpdateError::Unavailable(reason)) => match retries.next(&d_order.id) {
+                    Some(delay) => {
+                        error!("inventory", order = d_order.id, "{}, retrying in {:?}", reason, delay);
+                        backoff = backoff.max(delay);
+                        Settle::Requeue
+                    }
+                    None => {
+                        error!("inventory", order = d_order.id, "{}, giving up", reason);
+                        Settle::DeadLetter(format!("{} after {} attempts", reason, retry_policy().max_attempts))
+                    }
+                },
+            };
+        }
+        (outcomes, backoff)
+    }
+
+    // Apply a batch of orders, taking each item's lock once for the whole batch; one result per order,
+    // in the same order, with the history entry of offloads. Reservations are dropped and history
+    // recorded under the same locks.
+    pub fn update_inventory(inventory: &ItemTable, orders: &[Order], order_tx: &Sender<Order>) -> Vec<Result<Option<HistoryEntry>, UpdateError>> {
+        let codes: BTreeSet<&str> = orders.iter().map(|order| order.code.as_str()).collect();
+        inventory.with_items(&codes, |locked| {
+            orders.iter().map(|order| match locked.get_mut(order.code.as_str()) {
+                Some(state) if registry().is_cancelled(&order.id) => {
+                    state.reservations.remove(&order.id);
+                    Err(UpdateError::Cancelled)
+                }
+                Some(state) => {
+                    let result = InventoryManagement::apply_order(&mut state.item, order, order_tx);
+                    if !matches!(result, Err(UpdateError::Unavailable(_))) {
+                        state.reservations.remove(&order.id);  // Applied or never will be
+                    }
+                    if let Ok(Some(entry)) = &result {
+                        state.record(entry.clone());
+                    }
+                    result
+                }
+                None => {
+                    warn!("inventory", order = order.id, "Item not found: {}", order.code);
+                    registry().set_status(order, OrderStatus::Failed);
+                    Err(UpdateError::Rejected(format!("Item not found: {}", order.code)))
+                }
+            }).collect()
+        })
+    }
+
+    fn apply_order(item: &mut Item, order: &Order, order_tx: &Sender<Order>) -> Result<Option<HistoryEntry>, UpdateError> {
+        match order.order_type.as_str() {
+            // Counted once storage has put it away, so offloads never reserve boxes that did not fit in the racks
+            "supply" => return InventoryManagement::forward_to_storage(order, order_tx).map(|_| None),
+            "offload" if item.quantity < order.quantity => {
+                warn!("inventory", order = order.id, "Offload of {} boxes of {} rejected: only {} in stock",
+                        order.quantity, item.name, item.quantity);
+                registry().set_status(order, OrderStatus::Failed);
+                return Err(UpdateError::Rejected(format!("Insufficient stock: {} of {} boxes", item.quantity, order.quantity)));
+            }
+            "offload" => {
+                item.exit += order.quantity;
+                item.quantity -= order.quantity;
+            }
+            _ => {
+                warn!("inventory", order = order.id, "Unknown order type: {}", order.order_type);
+                registry().set_status(order, OrderStatus::Failed);
+                return Err(UpdateError::Rejected(format!("Unknown order type: {}", order.order_type)));
             }
-            println!("*Inventory Updated* for {}: {:?}", item.name, item);
-            order_tx.send(order.clone()).unwrap();
-        } else {
-            println!("Item not found for order: {:?}", order);
         }
+        info!("inventory", order = order.id, "Inventory updated for {}: quantity={} entry={} exit={}",
+                item.name, item.quantity, item.entry, item.exit);
+        bus().publish(Event::InventoryUpdated {
+            order: order.id.clone(), code: item.code.clone(), name: item.name.clone(),
+            quantity: item.quantity, entry: item.entry, exit: item.exit,
+        });
+        let entry = HistoryEntry {
+            order_id: order.id.clone(),
+            order_type: order.order_type.clone(),
+            quantity: order.quantity,
+            balance: item.quantity,
+            timestamp_us: now_micros(),
+        };
+        if let Err(e) = InventoryManagement::forward_to_storage(order, order_tx) {
+            // Storage management is gone: undo so a redelivery applies it exactly once
+            item.exit -= entry.quantity;
+            item.quantity += entry.quantity;
+            return Err(e);
+        }
+        Ok(Some(entry))
+    }
+
+    fn forward_to_storage(order: &Order, order_tx: &Sender<Order>) -> Result<(), UpdateError> {
+        let mut order = order.clone();
+        order.mark(Stage::InventoryUpdated);
+        order_tx.send(order).map_err(|_| UpdateError::Unavailable("Storage management is not running".to_string()))
     }
 
     pub fn check_inventory_stock(&self, order: &Order) -> bool {
-        let inventory = self.inventory.lock().unwrap();
-        if let Some(item) = inventory.iter().find(|i| i.code == order.code) {
-            // Check if the available quantity is enough for the offload order
-            if order.order_type == "offload" && item.quantity < order.quantity {
-                println!("❌ Order Declined! Insufficient stock for order {}. Items Requested: {}. Inventory balance: {}",
-                            order.index, order.quantity, item.quantity);
-                return false;
+        self.inventory.with_item(&order.code, |state| InventoryManagement::has_stock(state, order).is_some())
+            .unwrap_or_else(|| InventoryManagement::item_not_found(order))
+    }
+
+    // Check the order and hold its stock so later orders cannot promise the same boxes. Accepted offloads
+    // get a pick list, by the pick strategy of the item's category, from lots not promised to others;
+    // offloads the racks cannot cover in full, such as when the strategy leaves expired lots, are declined.
+    // The racks are locked inside the item lock, never the other way round.
+    pub fn reserve_stock(&self, order: &mut Order) -> bool {
+        let racks = &self.racks;
+        let code = order.code.clone();
+        self.inventory.with_item(&code, |state| {
+            let requested = InventoryManagement::has_stock(state, order);
+            if let (Some(quantity), "offload") = (requested, order.order_type.as_str()) {
+                if let Some(item) = catalog().get(&order.code) {
+                    let strategy = pick::for_category(&item.category).strategy();
+                    let promised = state.promised_picks();
+                    order.picks = racks.with_racks(|racks| pick::plan(racks, strategy.as_ref(), item, &promised, quantity, now_micros()));
+                    let pickable: i32 = order.picks.iter().map(|pick| pick.quantity).sum();
+                    if pickable < quantity {
+                        warn!("inventory", order = order.id, "Order declined: only {} of {} boxes can be picked by {}",
+                                pickable, quantity, strategy.name());
+                        order.picks.clear();
+                        return false;
+                    }
+                }
+                state.reservations.insert(order.id.clone(), Reservation { quantity, reserved_at: Instant::now(), picks: order.picks.clone() });
             }
-            true
-        } else {
-            println!("Item not found for order: {:?}", order);
-            false
+            requested.is_some()
+        }).unwrap_or_else(|| InventoryManagement::item_not_found(order))
+    }
+
+    // Cancellations only know the order id, so look through the items one lock at a time
+    pub fn release_stock(&self, order_id: &str) {
+        let mut released = false;
+        self.inventory.for_each(|state| released |= state.reservations.remove(order_id).is_some());
+        if released {
+            info!("inventory", order = order_id, "Stock reservation released");
         }
     }
 
-    pub fn storage_management(&self, order_rx: Receiver<Order>) {
+    // Stock on hand minus what is reserved for accepted offloads
+    pub fn available_stock(&self, code: &str) -> Option<i32> {
+        self.inventory.with_item(code, |state| state.available())
+    }
+
+    // The quantity requested in eaches if the order can be met, whatever unit it is in
+    fn has_stock(state: &mut ItemState, order: &Order) -> Option<i32> {
+        let requested = match catalog().to_eaches(&order.code, order.quantity, order.uom) {
+            Ok(requested) => requested,
+            Err(e) => {
+                warn!("inventory", order = order.id, "Order declined: {}", e);
+                return None;
+            }
+        };
+        let available = state.available();
+        // Check if the available quantity is enough for the offload order
+        if order.order_type == "offload" && available < requested {
+            warn!("inventory", order = order.id, "Order declined: insufficient stock. Items requested: {}. Available after reservations: {}",
+                        requested, available);
+            return None;
+        }
+        Some(requested)
+    }
+
+    fn item_not_found(order: &Order) -> bool {
+        warn!("inventory", order = order.id, "Item not found: {}", order.code);
+        false
+    }
+
+    // Consistent copies for reports and the API, taken without blocking updates for longer than the copy
+    pub fn inventory_snapshot(&self) -> Vec<Item> {
+        self.inventory.snapshot()
+    }
+
+    pub fn rack_snapshot(&self) -> Vec<Rack> {
+        self.racks.snapshot()
+    }
+
+    pub fn item(&self, code: &str) -> Option<Item> {
+        self.inventory.with_item(code, |state| state.item.clone())
+    }
+
+    pub fn item_history(&self, code: &str) -> Vec<HistoryEntry> {
+        self.inventory.with_item(code, |state| state.history.iter().cloned().collect()).unwrap_or_default()
+    }
+
+    pub fn storage_management(&self, order_rx: Receiver<Order>, putaway: PutawayKind) {
+        let strategy = putaway.strategy();
+        info!("storage", "Putaway strategy: {}", strategy.name());
+        let inventory_clone = Arc::clone(&self.inventory);
         let racks_clone = Arc::clone(&self.racks);
+        let stored_clone = Arc::clone(&self.stored_orders);
         thread::spawn(move || {
-            for order in order_rx {
-                let mut racks = racks_clone.lock().unwrap();
+            for mut order in order_rx {
+                let order_id = order.id.clone();
+                if stored_clone.is_duplicate(&order_id) {
+                    continue;
+                }
+                let Some(item) = catalog().get(&order.code) else {
+                    warn!("storage", order = order.id, "Item not in catalog: {}", order.code);
+                    registry().set_status(&order, OrderStatus::Failed);
+                    stored_clone.record(&order_id);
+                    continue;
+                };
                 match order.order_type.as_str() {
                     "supply" => {
-                        let mut remaining_quantity = order.quantity;
-                        for rack in racks.iter_mut() {
-                            let space_available = rack.max_capacity - rack.capacity;
-                            if space_available >= remaining_quantity {
-                                // If there is enough space in the current rack
-                                rack.capacity += remaining_quantity;
-                                println!("*Storage* {} Capacity: {}. Added {} boxes to {}", 
-                                        rack.name, rack.capacity, remaining_quantity, rack.name);
-                                break; // Break the loop as the supply has been placed
-                            } else {
-                                // If there is not enough space in the current rack
-                                rack.capacity = rack.max_capacity; // Fill up the rack
-                                remaining_quantity -= space_available; // Update the remaining quantity
-                                println!("*Storage* {} Capacity: {}. Added {} boxes to {}", 
-                                        rack.name, rack.capacity, space_available, rack.name);
+                        // Plan against every bin's opening, weight and volume before placing anything
+                        let velocity = if strategy.uses_velocity() { Velocity::of(&item.sku, &inventory_clone.snapshot()) } else { Velocity::B };
+                        let stored = racks_clone.with_racks(|racks| -> Result<(), PutawayError> {
+                            let placements = putaway::plan(racks, strategy.as_ref(), item, velocity, order.quantity)?;
+                            putaway::apply(racks, item, &placements, now_micros());
+                            for placement in &placements {
+                                let rack = &racks[placement.location.rack];
+                                let bin = rack.bin(placement.location);
+                                info!("storage", order = order.id, "Added {} boxes to {}. Capacity: {}",
+                                        placement.quantity, bin.name, rack.capacity);
+                                InventoryManagement::publish_rack_change(&order, rack, &bin.name, placement.quantity);
+                                metrics().add("wms_putaway_travel_meters_total", &[("strategy", strategy.name())],
+                                        2.0 * rack.receiving_distance_m(placement.location));
                             }
+                            Ok(())
+                        });
+                        if let Err(e) = stored {
+                            // The goods stay at receiving rather than overload a bin, and are not counted as stock
+                            metrics().inc("wms_putaway_rejected_total", &[("reason", e.reason())]);
+                            warn!("storage", order = order.id, "Putaway rejected for {} boxes of {}: {}", order.quantity, order.code, e);
+                            registry().set_status(&order, OrderStatus::Failed);
+                            stored_clone.record(&order_id);
+                            continue;
                         }
-                        println!();
+                        InventoryManagement::credit_supply(&inventory_clone, &order);
                     }
                     "offload" => {
                         let mut remaining_quantity = order.quantity;
-                        for rack in racks.iter_mut() {
-                            let items_to_remove = rack.capacity.min(remaining_quantity);
-                            rack.capacity -= items_to_remove;
-                            remaining_quantity -= items_to_remove;
-                            println!("*Storage* {} Capacity: {}. Offloaded {} boxes from {}",
-                                    rack.name, rack.capacity, items_to_remove, rack.name);
-                            if remaining_quantity == 0 {
-                                break; // If all items are removed, break the loop
+                        // Follow the pick list planned when the order was accepted
+                        let strategy = pick::for_category(&item.category).strategy();
+                        let promised = inventory_clone.with_item(&order.code, |state| state.promised_picks()).unwrap_or_default();
+                        racks_clone.with_racks(|racks| {
+                            for pick in pick::execute(racks, strategy.as_ref(), item, &order.picks, &promised, order.quantity, now_micros()) {
+                                let rack = &racks[pick.location.rack];
+                                remaining_quantity -= pick.quantity;
+                                info!("storage", order = order.id, "Offloaded {} boxes from {}. Capacity: {}",
+                                        pick.quantity, rack.bin(pick.location).name, rack.capacity);
+                                InventoryManagement::publish_rack_change(&order, rack, &rack.bin(pick.location).name, -pick.quantity);
+                                metrics().inc("wms_pick_lines_total", &[("strategy", strategy.name())]);
                             }
+                        });
+                        if remaining_quantity > 0 {
+                            warn!("storage", order = order.id, "Only {} of {} boxes of {} found in racks",
+                                    order.quantity - remaining_quantity, order.quantity, order.code);
                         }
-                        println!();
                     }
-                    _ => println!("Unknown order type: {:?}", order.order_type),
+                    _ => warn!("storage", order = order.id, "Unknown order type: {}", order.order_type),
                 }
+                order.mark(Stage::Stored);
+                latency::recorder().record(&order);
+                metrics().inc("wms_orders_completed_total", &[("order_type", &order.order_type)]);
+                registry().set_status(&order, OrderStatus::Completed);
+                stored_clone.record(&order_id);
             }
         });
     }
 
-    pub fn scheduled_inventory_report(&self) {
+    // Count a supply once it is in the racks
+    fn credit_supply(inventory: &ItemTable, order: &Order) {
+        inventory.with_item(&order.code, |state| {
+            let item = &mut state.item;
+            item.entry += order.quantity;
+            item.quantity += order.quantity;
+            info!("inventory", order = order.id, "Inventory updated for {}: quantity={} entry={} exit={}",
+                    item.name, item.quantity, item.entry, item.exit);
+            bus().publish(Event::InventoryUpdated {
+                order: order.id.clone(), code: item.code.clone(), name: item.name.clone(),
+                quantity: item.quantity, entry: item.entry, exit: item.exit,
+            });
+            let entry = HistoryEntry {
+                order_id: order.id.clone(),
+                order_type: order.order_type.clone(),
+                quantity: order.quantity,
+                balance: item.quantity,
+                timestamp_us: now_micros(),
+            };
+            state.record(entry);
+        });
+    }
+
+    fn publish_rack_change(order: &Order, rack: &Rack, bin: &str, change: i32) {
+        if change != 0 {
+            bus().publish(Event::RackChanged {
+                order: order.id.clone(), rack: rack.name.clone(), bin: bin.to_string(), code: order.code.clone(),
+                change, capacity: rack.capacity, fill_ratio: rack.fill_ratio(),
+            });
+        }
+    }
+
+    pub fn scheduled_inventory_report(&self, interval: Duration, latency_report: Option<String>) {
         let inventory_clone = Arc::clone(&self.inventory);
         let racks_clone = Arc::clone(&self.racks);
-        self.pool.execute_at_fixed_rate(Duration::from_secs(0), Duration::from_secs(20), move || {
-            let inventory = inventory_clone.lock().unwrap();
-            let racks = racks_clone.lock().unwrap();
-            println!();
-            println!("📝");
-            println!("=========================== Inventory Report ============================");
-            for item in inventory.iter() {
-                println!("{:?}", item);
-            }
-            println!("============================== Rack Status ==============================");
-            for rack in racks.iter() {
-                println!("{:?}", rack);
+        self.pool.execute_at_fixed_rate(Duration::from_secs(0), interval, move || {
+            // Print from snapshots so no lock is held while writing to the terminal
+            print_inventory_report(&inventory_clone.snapshot(), &racks_clone.snapshot());
+        });
+        self.pool.execute_at_fixed_rate(interval, interval, move || {
+            if let Some(path) = &latency_report {  // Export for comparison across runs
+                if let Err(e) = latency::recorder().export(path) {
+                    error!("latency", "Failed to export latency report: {}", e);
+                }
             }
-            println!("=========================================================================");
-            println!();
         });
     }
 }
                     
+
+// The demo warehouse: five racks of four levels of six pallet bins, receiving and shipping at opposite ends
+pub fn demo_racks() -> Vec<Rack> {
+    let bin = Dimensions { length_mm: 2700, width_mm: 1100, height_mm: 1600 };
+    let rack = |name: &str, receiving_m: f64| Rack { receiving_m, shipping_m: 60.0 - receiving_m, ..Rack::new(name, 4, 6, bin.clone(), 1000.0) };
+    vec![
+        rack("Rack A", 10.0),
+        rack("Rack B", 20.0),
+        rack("Rack C", 30.0),
+        rack("Rack D", 40.0),
+        Rack { hazardous: true, ..rack("Rack E", 50.0) },
+    ]
+}
+
+// The demo stock: 500 of everything in the catalog
+pub fn opening_stock() -> Vec<Item> {
+    catalog().items().map(|item| {
+        Item { code: item.sku.clone(), name: item.description.clone(), quantity: OPENING_STOCK, entry: 0, exit: 0}
+    }).collect()
+}
+
+pub fn rack_opening_stock(racks: &mut [MutexGuard<Rack>], strategy: &dyn PutawayStrategy, items: &[Item], received_at_us: u64) {
+    for stock in items {
+        let Some(item) = catalog().get(&stock.code) else { continue };
+        match putaway::plan(racks, strategy, item, Velocity::B, stock.quantity) {
+            Ok(placements) => putaway::apply(racks, item, &placements, received_at_us),
+            Err(e) => warn!("storage", "Opening stock of {} not racked: {}", item.sku, e),
+        }
+    }
+}
+
+// Print the periodic report, or log it one entry per line in JSON mode to keep the output machine readable
+pub fn print_inventory_report(inventory: &[Item], racks: &[Rack]) {
+    bus().publish(Event::ReportGenerated { items: inventory.len(), racks: racks.len() });
+    if logging::json_enabled() {
+        for item in inventory.iter() {
+            info!("report", "{:?}", item);
+        }
+        for rack in racks.iter() {
+            info!("report", "{}", rack);
+        }
+        for s in latency::recorder().summary() {
+            info!("report", "{:?}", s);
+        }
+        return;
+    }
+    println!();
+    println!("📝");
+    println!("=========================== Inventory Report ============================");
+    for item in inventory.iter() {
+        println!("{:?}", item);
+    }
+    println!("============================== Rack Status ==============================");
+    for rack in racks.iter() {
+        println!("{}", rack);
+    }
+    latency::recorder().print_report();
+    println!("=========================================================================");
+    println!();
+}
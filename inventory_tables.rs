@@ -0,0 +1,132 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::sync::{Mutex, MutexGuard, RwLock};
+use std::time::{Duration, Instant};
+use crate::inventory_management::{HistoryEntry, Item, Rack};
+use crate::order_registry::registry;
+use crate::pick::Pick;
+
+pub const MAX_HISTORY_PER_ITEM: usize = 1000;
+pub const RESERVATION_TIMEOUT: Duration = Duration::from_secs(600);   // Stock held for orders that never arrive is freed after this
+
+// Stock held for an accepted offload order until inventory applies it
+#[derive(Debug, Clone)]
+pub struct Reservation {
+    pub quantity: i32,
+    pub reserved_at: Instant,
+    pub picks: Vec<Pick>,   // The boxes promised to the order
+}
+
+// Everything known about one item, behind the item's own lock
+#[derive(Debug)]
+pub struct ItemState {
+    pub item: Item,
+    pub reservations: HashMap<String, Reservation>,    // By order id
+    pub history: VecDeque<HistoryEntry>,
+}
+
+impl ItemState {
+    // Stock on hand minus what is reserved for accepted offloads. Reservations of orders that failed or
+    // were cancelled on the way are dropped; orders waiting in an outbox keep theirs.
+    pub fn available(&mut self) -> i32 {
+        self.reservations.retain(|id, r| r.reserved_at.elapsed() < RESERVATION_TIMEOUT && !registry().is_abandoned(id));
+        self.item.quantity - self.reservations.values().map(|r| r.quantity).sum::<i32>()
+    }
+
+    // Boxes picked for the offloads holding reservations
+    pub fn promised_picks(&self) -> Vec<Pick> {
+        self.reservations.values().flat_map(|r| r.picks.iter().cloned()).collect()
+    }
+
+    pub fn record(&mut self, entry: HistoryEntry) {
+        self.history.push_back(entry);
+        if self.history.len() > MAX_HISTORY_PER_ITEM {
+            self.history.pop_front();
+        }
+    }
+}
+
+// Items keyed by code, each with its own lock, so checks and updates of different items never wait on
+// each other. The map lock is only taken for writing when an item is added. Anything locking several
+// items does so in code order, which rules out deadlocks and lets a snapshot see every update whole.
+pub struct ItemTable {
+    items: RwLock<BTreeMap<String, Mutex<ItemState>>>,
+}
+
+impl ItemTable {
+    pub fn new(items: Vec<Item>) -> Self {
+        let table = ItemTable { items: RwLock::new(BTreeMap::new()) };
+        for item in items {
+            table.insert(item);
+        }
+        table
+    }
+
+    // Add an item, or replace it along with its reservations and history
+    pub fn insert(&self, item: Item) {
+        let state = ItemState { item, reservations: HashMap::new(), history: VecDeque::new() };
+        self.items.write().unwrap().insert(state.item.code.clone(), Mutex::new(state));
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Run f with only this item locked; None for unknown items
+    pub fn with_item<R>(&self, code: &str, f: impl FnOnce(&mut ItemState) -> R) -> Option<R> {
+        let items = self.items.read().unwrap();
+        items.get(code).map(|state| f(&mut state.lock().unwrap()))
+    }
+
+    // Run f with all of the given items locked at once; unknown codes are left out
+    pub fn with_items<R>(&self, codes: &BTreeSet<&str>, f: impl FnOnce(&mut BTreeMap<&str, MutexGuard<'_, ItemState>>) -> R) -> R {
+        let items = self.items.read().unwrap();
+        let mut locked = BTreeMap::new();
+        for code in codes {     // BTreeSet iterates in code order
+            if let Some(state) = items.get(*code) {
+                locked.insert(*code, state.lock().unwrap());
+            }
+        }
+        f(&mut locked)
+    }
+
+    // Run f on each item in turn, holding one lock at a time
+    pub fn for_each(&self, mut f: impl FnMut(&mut ItemState)) {
+        for state in self.items.read().unwrap().values() {
+            f(&mut state.lock().unwrap());
+        }
+    }
+
+    // All items as of one instant: every item lock is held while copying, none while the caller uses the copy
+    pub fn snapshot(&self) -> Vec<Item> {
+        let items = self.items.read().unwrap();
+        let locked: Vec<MutexGuard<ItemState>> = items.values().map(|state| state.lock().unwrap()).collect();
+        locked.iter().map(|state| state.item.clone()).collect()
+    }
+}
+
+// Racks with a lock each. Storage holds every rack's lock while it plans and places an order, taking
+// them in rack order like snapshots do, so a snapshot never shows an order half stored.
+pub struct RackTable {
+    racks: Vec<Mutex<Rack>>,
+}
+
+impl RackTable {
+    pub fn new(racks: Vec<Rack>) -> Self {
+        RackTable { racks: racks.into_iter().map(Mutex::new).collect() }
+    }
+
+    // Run f with all racks locked, in rack order
+    pub fn with_racks<R>(&self, f: impl FnOnce(&mut [MutexGuard<Rack>]) -> R) -> R {
+        let mut locked: Vec<MutexGuard<Rack>> = self.racks.iter().map(|rack| rack.lock().unwrap()).collect();
+        f(&mut locked)
+    }
+
+    pub fn snapshot(&self) -> Vec<Rack> {
+        let locked: Vec<MutexGuard<Rack>> = self.racks.iter().map(|rack| rack.lock().unwrap()).collect();
+        locked.iter().map(|rack| (*rack).clone()).collect()
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use hdrhistogram::Histogram;
+use serde::{Serialize, Deserialize};
+use crate::order_processing::Order;
+
+// Stages an order passes through, in pipeline order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Created,            // Generated in Order::start
+    Dispatched,         // Published to order_queue
+    TransportStarted,   // Forklift assigned
+    TransportCompleted, // Forklift arrived, published to transport_queue
+    InventoryUpdated,   // Applied by update_inventory
+    Stored,             // Placed/removed by storage_management
+}
+
+// Wall clock timestamps (microseconds since UNIX epoch) carried with the order through every queue
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Timestamps {
+    pub created: Option<u64>,
+    pub dispatched: Option<u64>,
+    pub transport_started: Option<u64>,
+    pub transport_completed: Option<u64>,
+    pub inventory_updated: Option<u64>,
+    pub stored: Option<u64>,
+}
+
+impl Timestamps {
+    pub fn mark(&mut self, stage: Stage) {
+        let now = Some(now_micros());
+        match stage {
+            Stage::Created => self.created = now,
+            Stage::Dispatched => self.dispatched = now,
+            Stage::TransportStarted => self.transport_started = now,
+            Stage::TransportCompleted => self.transport_completed = now,
+            Stage::InventoryUpdated => self.inventory_updated = now,
+            Stage::Stored => self.stored = now,
+        }
+    }
+}
+
+// Intervals measured between two stage timestamps
+const SEGMENTS: [&str; 6] = ["dispatch", "queue_wait", "transport", "processing", "storage", "end_to_end"];
+
+fn segments(t: &Timestamps) -> [(&'static str, Option<u64>, Option<u64>); 6] {
+    [
+        (SEGMENTS[0], t.created, t.dispatched),
+        (SEGMENTS[1], t.dispatched, t.transport_started),
+        (SEGMENTS[2], t.transport_started, t.transport_completed),
+        (SEGMENTS[3], t.transport_completed, t.inventory_updated),
+        (SEGMENTS[4], t.inventory_updated, t.stored),
+        (SEGMENTS[5], t.created, t.stored),
+    ]
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencySummary {
+    pub segment: String,
+    pub order_type: String,
+    pub count: u64,
+    pub min_us: u64,
+    pub mean_us: f64,
+    pub p50_us: u64,
+    pub p95_us: u64,
+    pub p99_us: u64,
+    pub max_us: u64,
+    pub jitter_us: f64, // Standard deviation
+}
+
+pub struct LatencyRecorder {
+    histograms: Mutex<BTreeMap<(String, String), Histogram<u64>>>,
+}
+
+impl LatencyRecorder {
+    fn new() -> Self {
+        LatencyRecorder { histograms: Mutex::new(BTreeMap::new()) }
+    }
+
+    // Record every completed segment of a finished order
+    pub fn record(&self, order: &Order) {
+        let mut histograms = self.histograms.lock().unwrap();
+        for (segment, from, to) in segments(&order.timestamps) {
+            if let (Some(from), Some(to)) = (from, to) {
+                let histogram = histograms
+                    .entry((segment.to_string(), order.order_type.clone()))
+                    .or_insert_with(|| Histogram::new_with_bounds(1, 3_600_000_000, 3).unwrap()); // 1µs to 1 hour, 3 significant digits
+                histogram.saturating_record(to.saturating_sub(from).max(1));
+            }
+        }
+    }
+
+    pub fn summary(&self) -> Vec<LatencySummary> {
+        let histograms = self.histograms.lock().unwrap();
+        let mut summary: Vec<LatencySummary> = histograms.iter().map(|((segment, order_type), h)| LatencySummary {
+            segment: segment.clone(),
+            order_type: order_type.clone(),
+            count: h.len(),
+            min_us: h.min(),
+            mean_us: h.mean(),
+            p50_us: h.value_at_quantile(0.50),
+            p95_us: h.value_at_quantile(0.95),
+            p99_us: h.value_at_quantile(0.99),
+            max_us: h.max(),
+            jitter_us: h.stdev(),
+        }).collect();
+        // Keep pipeline order rather than alphabetical order
+        summary.sort_by_key(|s| (SEGMENTS.iter().position(|seg| *seg == s.segment), s.order_type.clone()));
+        summary
+    }
+
+    pub fn print_report(&self) {
+        println!("=========================== Pipeline Latency (ms) =======================");
+        println!("{:<11} {:<8} {:>6} {:>9} {:>9} {:>9} {:>9} {:>9}", "stage", "type", "count", "p50", "p95", "p99", "max", "jitter");
+        for s in self.summary() {
+            println!("{:<11} {:<8} {:>6} {:>9.1} {:>9.1} {:>9.1} {:>9.1} {:>9.1}",
+                    s.segment, s.order_type, s.count, ms(s.p50_us), ms(s.p95_us), ms(s.p99_us), ms(s.max_us), s.jitter_us / 1000.0);
+        }
+    }
+
+    // Write the current summary as JSON so separate runs can be compared
+    pub fn export(&self, path: &str) -> std::io::Result<()> {
+        let report = serde_json::json!({
+            "generated_at_us": now_micros(),
+            "latency": self.summary(),
+        });
+        fs::write(path, serde_json::to_string_pretty(&report)?)
+    }
+}
+
+pub fn recorder() -> &'static LatencyRecorder {
+    static RECORDER: OnceLock<LatencyRecorder> = OnceLock::new();
+    RECORDER.get_or_init(LatencyRecorder::new)
+}
+
+pub fn now_micros() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_micros() as u64).unwrap_or(0)
+}
+
+fn ms(us: u64) -> f64 {
+    us as f64 / 1000.0
+}
@@ -0,0 +1,27 @@
+// Listing all the mods needed; the binaries in bin/ and main.rs use them through this library
+#[macro_use]
+pub mod logging;
+pub mod inventory_management;
+pub mod inventory_tables;
+pub mod order_processing;
+pub mod goods_transportation;
+pub mod rabbitmq;
+pub mod async_rabbitmq;
+pub mod latency;
+pub mod metrics;
+pub mod order_registry;
+pub mod api;
+pub mod events;
+pub mod dashboard;
+pub mod config;
+pub mod cli;
+pub mod dedup;
+pub mod outbox;
+pub mod topology;
+pub mod stock_rpc;
+pub mod trip_rpc;
+pub mod services;
+pub mod codec;
+pub mod catalog;
+pub mod putaway;
+pub mod pick;
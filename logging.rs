@@ -0,0 +1,139 @@
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+        }
+    }
+}
+
+struct Logger {
+    level: Level,
+    json: bool,
+}
+
+// Configured from WMS_LOG_LEVEL (error|warn|info|debug) and WMS_LOG_FORMAT (text|json)
+fn logger() -> &'static Logger {
+    static LOGGER: OnceLock<Logger> = OnceLock::new();
+    LOGGER.get_or_init(|| {
+        let level = match std::env::var("WMS_LOG_LEVEL").unwrap_or_default().to_lowercase().as_str() {
+            "error" => Level::Error,
+            "warn" => Level::Warn,
+            "debug" => Level::Debug,
+            _ => Level::Info,
+        };
+        let json = std::env::var("WMS_LOG_FORMAT").map(|f| f.eq_ignore_ascii_case("json")).unwrap_or(false);
+        Logger { level, json }
+    })
+}
+
+static LOG_FILE: OnceLock<Mutex<File>> = OnceLock::new();
+
+// Append all events to a file instead of the terminal (used while the dashboard owns the screen)
+pub fn log_to_file(path: &str) -> std::io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let _ = LOG_FILE.set(Mutex::new(file));
+    Ok(())
+}
+
+pub fn json_enabled() -> bool {
+    logger().json
+}
+
+pub fn enabled(level: Level) -> bool {
+    level <= logger().level
+}
+
+// Write one event as a single line so output from concurrent threads never interleaves
+pub fn log(level: Level, component: &str, order: Option<&str>, message: &str) {
+    let logger = logger();
+    if level > logger.level {
+        return;
+    }
+    let ts_ms = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+    let current = thread::current();
+    let thread_name = current.name().unwrap_or("unnamed");
+
+    let mut line = String::new();
+    if logger.json {
+        let mut event = serde_json::json!({
+            "ts_ms": ts_ms as u64,
+            "level": level.as_str(),
+            "component": component,
+            "thread": thread_name,
+            "message": message,
+        });
+        if let Some(order) = order {
+            event["order"] = serde_json::Value::from(order);
+        }
+        line.push_str(&event.to_string());
+    } else {
+        write!(line, "{}.{:03} {:<5} [{}]", ts_ms / 1000, ts_ms % 1000, level.as_str(), component).unwrap();
+        if let Some(order) = order {
+            write!(line, " order={}", order).unwrap();
+        }
+        write!(line, " {}", message).unwrap();
+    }
+    line.push('\n');
+
+    let result = if let Some(file) = LOG_FILE.get() {
+        file.lock().unwrap().write_all(line.as_bytes())
+    } else if level <= Level::Warn {
+        std::io::stderr().lock().write_all(line.as_bytes())
+    } else {
+        std::io::stdout().lock().write_all(line.as_bytes())
+    };
+    let _ = result; // Nowhere left to report a failed log write
+}
+
+// log_event!(Level::Info, "component", order = id, "format {}", args) or without the order field
+#[macro_export]
+macro_rules! log_event {
+    ($level:expr, $component:expr, order = $order:expr, $($arg:tt)+) => {
+        if $crate::logging::enabled($level) {
+            $crate::logging::log($level, $component, Some(&$order.to_string()), &format!($($arg)+))
+        }
+    };
+    ($level:expr, $component:expr, $($arg:tt)+) => {
+        if $crate::logging::enabled($level) {
+            $crate::logging::log($level, $component, None, &format!($($arg)+))
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)+) => { $crate::log_event!($crate::logging::Level::Error, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)+) => { $crate::log_event!($crate::logging::Level::Warn, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)+) => { $crate::log_event!($crate::logging::Level::Info, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => { $crate::log_event!($crate::logging::Level::Debug, $($arg)+) };
+}
@@ -1,42 +1,114 @@
-// Listing all the mods needed 
-mod inventory_management;
-mod order_processing;
-mod goods_transportation;
-mod rabbitmq;
-
 //Listing all crates, functions and libraries needed
 use std::thread;
 use std::time::Duration;
-use std::sync::mpsc::channel;
 use std::sync::Arc;
-use crate::inventory_management::InventoryManagement;
-use order_processing::Order;
-use goods_transportation::GoodsTransportation;
+use clap::Parser;
+use wms::inventory_management::InventoryManagement;
+use wms::goods_transportation::GoodsTransportation;
+use wms::config::{Config, StockCheck};
+use wms::stock_rpc::StockService;
+use wms::trip_rpc::LocalClaims;
+use wms::cli::{self, Cli, Command, ReportFormat, RunArgs};
+use wms::api::{self, ApiParts, OrderIntake};
+use wms::services::{self, load_config};
+use wms::{dashboard, latency, logging, metrics};
 
 fn main() {
-    //Create channel for storage management
-    let (order_tx, order_rx) = channel::<Order>();
-    
-    // Create all queues of RabbitMQ
-    let order_queue: String = "order_queue".to_string();
-    let transport_queue = "transport_queue".to_string();
-
-    // Initiate the systems
-    let inventory_management = Arc::new(InventoryManagement::new());
-    let inventory_management_clone = Arc::clone(&inventory_management);
-    let goods_transportation = Arc::new(GoodsTransportation::new());
-
-    // Start the systems
-    Order::start(inventory_management_clone);
-    goods_transportation.start(order_queue);
-    inventory_management.start(transport_queue,order_tx);
-    inventory_management.storage_management(order_rx);
-    inventory_management.scheduled_inventory_report();
-    
+    let cli = Cli::parse();
+    let result = match cli.command.unwrap_or(Command::Run(RunArgs::default())) {
+        Command::Run(args) => load_config(None, cli.config.as_deref()).map(|config| run(&config, &args)),
+        Command::SubmitOrder { code, quantity, order_type } => cli::submit_order(&cli.api, &code, quantity, &order_type),
+        Command::Stock { code } => cli::stock(&cli.api, code.as_deref()),
+        Command::Racks => cli::racks(&cli.api),
+        Command::Replay { file } => cli::replay(&cli.api, &file),
+        Command::Report { format } => cli::report(&cli.api, format),
+        Command::Simulate { seed, format, compare_putaway: true, orders, .. } => {
+            load_config(None, cli.config.as_deref()).map(|_| cli::compare_putaway(orders, seed.unwrap_or(0), format))
+        }
+        Command::Simulate { seed, duration, format, .. } => {
+            load_config(None, cli.config.as_deref()).map(|config| simulate(&config, seed, duration, format))
+        }
+        Command::CheckConfig => cli::check_config(cli.config.as_deref()),
+        Command::Dlq { action } => load_config(None, cli.config.as_deref()).and_then(|_| cli::dlq(action)),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+// Start the systems in the background and return handles to their shared state
+fn start_systems(config: &Config, args: &RunArgs, seed: Option<u64>) -> (Arc<InventoryManagement>, Arc<GoodsTransportation>) {
+    // All services in one process for development; see bin/ for running them separately
+    if let Err(e) = services::prepare_broker(config) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    let inventory_management = services::start_inventory(config);
+    let goods_transportation = if args.no_transport {
+        Arc::new(GoodsTransportation::new())
+    } else {
+        services::start_transport(config, Arc::new(LocalClaims), seed)  // Intake is in this process
+    };
+
+    // Order intake reserves stock directly or through the inventory service's stock queue
+    let stock: Arc<dyn StockService> = match config.stock_check {
+        StockCheck::Local => Arc::clone(&inventory_management) as Arc<dyn StockService>,
+        StockCheck::Broker => Arc::new(services::stock_client(config)),
+    };
+    if !args.no_generator {
+        services::start_generator(config, Arc::clone(&stock), seed);
+    }
+    if !args.no_reporter && !args.tui {
+        inventory_management.scheduled_inventory_report(Duration::from_secs(config.report_interval_secs), config.latency_report.clone());
+    }
+
+    // Expose metrics for a local Prometheus to scrape
+    metrics::serve(&config.metrics_addr, Some(Arc::clone(&inventory_management)), Some(Arc::clone(&goods_transportation)));
+
+    // Serve the HTTP API for inventory queries and order submission
+    api::serve(&config.api_addr, ApiParts {
+        service: "wms",
+        orders: Some(OrderIntake { queue: config.order_queue.clone(), stock }),
+        inventory: Some(Arc::clone(&inventory_management)),
+        transport: Some(Arc::clone(&goods_transportation)),
+    });
+
+    (inventory_management, goods_transportation)
+}
+
+fn run(config: &Config, args: &RunArgs) {
+    // The dashboard owns the terminal, so logs go to a file instead
+    if args.tui {
+        if let Err(e) = logging::log_to_file(&config.log_file) {
+            eprintln!("Failed to open log file {}: {}", config.log_file, e);
+            return;
+        }
+    }
+
+    let (inventory_management, goods_transportation) = start_systems(config, args, None);
+
+    if args.tui {
+        if let Err(e) = dashboard::run(inventory_management, goods_transportation) {
+            eprintln!("Dashboard failed: {}", e);
+        }
+        std::process::exit(0);  // Stop the background systems with the dashboard
+    }
+
     // Loop the main thread to keep the simulation running
     loop {
         thread::sleep(Duration::from_secs(60));
     }
-}   
-
+}
 
+// Run for a fixed duration with a reproducible order stream, then print the final state
+fn simulate(config: &Config, seed: Option<u64>, duration: u64, format: ReportFormat) {
+    let args = RunArgs { no_reporter: true, ..RunArgs::default() };
+    let (inventory_management, _goods_transportation) = start_systems(config, &args, seed);
+    thread::sleep(Duration::from_secs(duration));
+    print!("{}", cli::format_report(&inventory_management.inventory_snapshot(), &inventory_management.rack_snapshot(), format));
+    if format == ReportFormat::Text {
+        latency::recorder().print_report();
+    }
+    std::process::exit(0);
+}
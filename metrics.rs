@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+use tiny_http::{Header, Response, Server};
+use crate::goods_transportation::GoodsTransportation;
+use crate::inventory_management::InventoryManagement;
+use crate::latency;
+use crate::rabbitmq;
+
+const QUEUE_DEPTH_INTERVAL: Duration = Duration::from_secs(5);
+
+// Help text for every metric updated from the pipeline threads
+const HELP: [(&str, &str, &str); 19] = [
+    ("wms_orders_generated_total", "counter", "Orders generated by type"),
+    ("wms_orders_declined_total", "counter", "Orders declined at intake by type"),
+    ("wms_orders_completed_total", "counter", "Orders stored or offloaded by type"),
+    ("wms_putaway_rejected_total", "counter", "Supplies that did not fit the racks by reason"),
+    ("wms_putaway_travel_meters_total", "counter", "Round-trip metres from receiving to the bins filled, by putaway strategy"),
+    ("wms_pick_lines_total", "counter", "Bins picked from for offloads by pick strategy"),
+    ("wms_message_publish_failures_total", "counter", "Failed publishes by queue"),
+    ("wms_messages_unroutable_total", "counter", "Publishes returned by the broker because no queue was bound, by queue"),
+    ("wms_outbox_depth", "gauge", "Messages waiting in the local outbox for a confirmed publish"),
+    ("wms_message_consume_failures_total", "counter", "Received messages that could not be processed by queue"),
+    ("wms_batches_total", "counter", "Delivery batches received by queue"),
+    ("wms_batched_messages_total", "counter", "Messages received in delivery batches by queue; divide by wms_batches_total for the mean batch size"),
+    ("wms_messages_dead_lettered_total", "counter", "Messages moved to a dead-letter queue by source queue"),
+    ("wms_duplicate_messages_total", "counter", "Duplicate orders detected and skipped by stage"),
+    ("wms_forklift_busy_seconds_total", "counter", "Seconds each forklift spent transporting"),
+    ("wms_transport_workers", "gauge", "Threads in the transport worker pool"),
+    ("wms_transport_trips_in_progress", "gauge", "Forklift trips scheduled on the transport pool and not yet completed"),
+    ("wms_dispatch_queue_depth", "gauge", "Orders ready on order_queue, as reported by the broker"),
+    ("wms_events_dropped_total", "counter", "Events not delivered to a subscriber that had fallen too far behind, by type"),
+];
+
+pub struct Metrics {
+    values: Mutex<BTreeMap<&'static str, BTreeMap<String, f64>>>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        Metrics { values: Mutex::new(BTreeMap::new()) }
+    }
+
+    pub fn add(&self, name: &'static str, labels: &[(&str, &str)], value: f64) {
+        let mut values = self.values.lock().unwrap();
+        *values.entry(name).or_default().entry(format_labels(labels)).or_insert(0.0) += value;
+    }
+
+    pub fn set(&self, name: &'static str, labels: &[(&str, &str)], value: f64) {
+        let mut values = self.values.lock().unwrap();
+        values.entry(name).or_default().insert(format_labels(labels), value);
+    }
+
+    pub fn inc(&self, name: &'static str, labels: &[(&str, &str)]) {
+        self.add(name, labels, 1.0);
+    }
+
+    pub fn get(&self, name: &'static str, labels: &[(&str, &str)]) -> f64 {
+        let values = self.values.lock().unwrap();
+        values.get(name).and_then(|v| v.get(&format_labels(labels))).copied().unwrap_or(0.0)
+    }
+
+    pub fn dec(&self, name: &'static str, labels: &[(&str, &str)]) {
+        self.add(name, labels, -1.0);
+    }
+
+    // Render all metrics in the Prometheus text exposition format; gauges of systems not in this process are left out
+    pub fn render(&self, inventory_management: Option<&InventoryManagement>, goods_transportation: Option<&GoodsTransportation>) -> String {
+        let mut out = String::new();
+        {
+            let values = self.values.lock().unwrap();
+            for (name, kind, help) in HELP {
+                writeln!(out, "# HELP {} {}\n# TYPE {} {}", name, help, name, kind).unwrap();
+                for (labels, value) in values.get(name).into_iter().flatten() {
+                    writeln!(out, "{}{} {}", name, labels, value).unwrap();
+                }
+            }
+        }
+
+        if let Some(inventory_management) = inventory_management {
+            writeln!(out, "# HELP wms_item_stock Current stock per item\n# TYPE wms_item_stock gauge").unwrap();
+            for item in inventory_management.inventory_snapshot() {
+                writeln!(out, "wms_item_stock{} {}", format_labels(&[("code", &item.code), ("name", &item.name)]), item.quantity).unwrap();
+            }
+
+            writeln!(out, "# HELP wms_rack_fill_ratio Rack weight or volume used, whichever is fuller, as a fraction of its limit\n# TYPE wms_rack_fill_ratio gauge").unwrap();
+            for rack in inventory_management.rack_snapshot() {
+                writeln!(out, "wms_rack_fill_ratio{} {}", format_labels(&[("rack", &rack.name)]), rack.fill_ratio()).unwrap();
+            }
+        }
+
+        if let Some(goods_transportation) = goods_transportation {
+            let forklifts = goods_transportation.forklift_status();
+            let in_use = forklifts.iter().filter(|f| f.in_use).count();
+            writeln!(out, "# HELP wms_forklift_busy Whether each forklift is currently transporting\n# TYPE wms_forklift_busy gauge").unwrap();
+            for forklift in &forklifts {
+                writeln!(out, "wms_forklift_busy{} {}", format_labels(&[("forklift", &forklift.name)]), forklift.in_use as u8).unwrap();
+            }
+            writeln!(out, "# HELP wms_forklift_utilization Fraction of forklifts currently in use\n# TYPE wms_forklift_utilization gauge").unwrap();
+            writeln!(out, "wms_forklift_utilization {}", if forklifts.is_empty() { 0.0 } else { in_use as f64 / forklifts.len() as f64 }).unwrap();
+        }
+
+        writeln!(out, "# HELP wms_pipeline_latency_seconds Order pipeline latency per stage and order type\n# TYPE wms_pipeline_latency_seconds summary").unwrap();
+        for s in latency::recorder().summary() {
+            for (quantile, value) in [("0.5", s.p50_us), ("0.95", s.p95_us), ("0.99", s.p99_us), ("1", s.max_us)] {
+                writeln!(out, "wms_pipeline_latency_seconds{} {}",
+                        format_labels(&[("stage", &s.segment), ("order_type", &s.order_type), ("quantile", quantile)]), value as f64 / 1e6).unwrap();
+            }
+            let labels = format_labels(&[("stage", &s.segment), ("order_type", &s.order_type)]);
+            writeln!(out, "wms_pipeline_latency_seconds_sum{} {}", labels, s.mean_us * s.count as f64 / 1e6).unwrap();
+            writeln!(out, "wms_pipeline_latency_seconds_count{} {}", labels, s.count).unwrap();
+        }
+        out
+    }
+}
+
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
+
+// Serve GET /metrics for a local Prometheus to scrape
+pub fn serve(addr: &str, inventory_management: Option<Arc<InventoryManagement>>, goods_transportation: Option<Arc<GoodsTransportation>>) {
+    let server = match Server::http(addr) {
+        Ok(server) => server,
+        Err(e) => {
+            error!("metrics", "Failed to start metrics endpoint on {}: {}", addr, e);
+            return;
+        }
+    };
+    info!("metrics", "Metrics available at http://{}/metrics", addr);
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let response = if request.url() == "/metrics" {
+                let body = metrics().render(inventory_management.as_deref(), goods_transportation.as_deref());
+                Response::from_string(body)
+                    .with_header("Content-Type: text/plain; version=0.0.4".parse::<Header>().unwrap())
+            } else {
+                Response::from_string("Not Found").with_status_code(404)
+            };
+            if let Err(e) = request.respond(response) {
+                error!("metrics", "Failed to respond to metrics request: {}", e);
+            }
+        }
+    });
+}
+
+// Keep wms_dispatch_queue_depth at the broker's count for the queue, which stays right with the services split up
+pub fn follow_queue_depth(queue: String) {
+    thread::spawn(move || {
+        loop {
+            match rabbitmq::queue_depth(&queue) {
+                Ok(depth) => metrics().set("wms_dispatch_queue_depth", &[], depth as f64),
+                Err(e) => debug!("metrics", "Failed to read the depth of {}: {}", queue, e),
+            }
+            thread::sleep(QUEUE_DEPTH_INTERVAL);
+        }
+    });
+}
+
+fn format_labels(labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let pairs: Vec<String> = labels.iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, v.replace('\\', "\\\\").replace('"', "\\\"")))
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
@@ -1,6 +1,6 @@
 extern crate bma_benchmark;
 use bma_benchmark::benchmark;
-use criterion::{black_box, Criterion, criterion_group, criterion_main};
+use criterion::{black_box, BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
 use std::sync::{Arc, Mutex};
 use std::sync::mpsc::channel;
 use std::thread;
@@ -8,6 +8,7 @@ use std::time::Duration;
 use rand::Rng;
 use amiquip::{Connection, Exchange, Publish, Result, ConsumerMessage, ConsumerOptions, QueueDeclareOptions};
 use serde::{Serialize, Deserialize};
+use wms::codec::CODECS;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct Order {
@@ -130,7 +131,155 @@ fn message_receiving_benchmark(c: &mut Criterion) {
     }));
 }
 
-criterion_group!(benches, order_generation_benchmark, inventory_check_benchmark, message_sending_benchmark, message_receiving_benchmark);
+// Encode and decode one order with each codec, and report the encoded size for comparison
+fn codec_benchmark(c: &mut Criterion) {
+    let order = wms::order_processing::Order::new("001".to_string(), 250, "offload".to_string());
+    for codec in CODECS {
+        let body = codec.encode(&order).unwrap();
+        println!("{:?}: {} bytes", codec, body.len());
+        c.bench_function(&format!("encode_{:?}", codec), |b| b.iter(|| {
+            black_box(codec.encode(&order).unwrap());
+        }));
+        c.bench_function(&format!("decode_{:?}", codec), |b| b.iter(|| {
+            black_box(codec.decode::<wms::order_processing::Order>(&body).unwrap());
+        }));
+    }
+}
+
+const BATCH_SIZES: [usize; 4] = [1, 10, 100, 1000];
+
+fn batch_orders(size: usize) -> Vec<wms::order_processing::Order> {
+    (0..size).map(|i| {
+        let order_type = if i % 2 == 0 { "supply" } else { "offload" };
+        wms::order_processing::Order::new("001".to_string(), 10, order_type.to_string())
+    }).collect()
+}
+
+// Orders/second applied to inventory, locking once per order versus once per batch
+fn inventory_batch_benchmark(c: &mut Criterion) {
+    let inventory = wms::inventory_tables::ItemTable::new(wms::inventory_management::InventoryManagement::new().inventory_snapshot());
+    let (order_tx, order_rx) = channel();
+    let mut group = c.benchmark_group("inventory_update");
+    for size in BATCH_SIZES {
+        let orders = batch_orders(size);
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::new("lock_per_order", size), &orders, |b, orders| b.iter(|| {
+            for order in orders.chunks(1) {
+                black_box(wms::inventory_management::InventoryManagement::update_inventory(&inventory, order, &order_tx));
+            }
+            order_rx.try_iter().count()
+        }));
+        group.bench_with_input(BenchmarkId::new("lock_per_batch", size), &orders, |b, orders| b.iter(|| {
+            black_box(wms::inventory_management::InventoryManagement::update_inventory(&inventory, orders, &order_tx));
+            order_rx.try_iter().count()
+        }));
+    }
+    group.finish();
+}
+
+const CONTENTION_SKUS: usize = 5000;
+const CONTENTION_THREADS: [usize; 4] = [1, 4, 16, 64];
+const OPS_PER_THREAD: usize = 1000;
+
+// Stock checks from many threads over thousands of SKUs: one mutex over the whole list (as before)
+// versus a lock per item
+fn contention_benchmark(c: &mut Criterion) {
+    let codes: Vec<String> = (0..CONTENTION_SKUS).map(|i| format!("{:05}", i)).collect();
+    let global = InventoryManagement {
+        inventory: Arc::new(Mutex::new(codes.iter().map(|code| Item { code: code.clone(), name: code.clone(), quantity: 500, entry: 0, exit: 0 }).collect())),
+    };
+    let items = codes.iter().map(|code| wms::inventory_management::Item { code: code.clone(), name: code.clone(), quantity: 500, entry: 0, exit: 0 }).collect();
+    let per_item = wms::inventory_management::InventoryManagement::with_stock(items, Vec::new());
+    let global_orders: Vec<Order> = codes.iter().enumerate()
+        .map(|(i, code)| Order { index: i as i32, code: code.clone(), quantity: 10, order_type: "offload".to_string() }).collect();
+    let orders: Vec<wms::order_processing::Order> = codes.iter().step_by(10)
+        .map(|code| wms::order_processing::Order::new(code.clone(), 10, "offload".to_string())).collect();
+
+    let mut group = c.benchmark_group("stock_check_contention");
+    group.sample_size(10);
+    for threads in CONTENTION_THREADS {
+        group.throughput(Throughput::Elements((threads * OPS_PER_THREAD) as u64));
+        group.bench_with_input(BenchmarkId::new("global_mutex", threads), &threads, |b, &threads| b.iter(|| {
+            thread::scope(|s| {
+                for t in 0..threads {
+                    let (global, global_orders) = (&global, &global_orders);
+                    s.spawn(move || {
+                        for i in 0..OPS_PER_THREAD {
+                            black_box(global.check_inventory_stock(&global_orders[(t * 7919 + i * 31) % global_orders.len()]));
+                        }
+                    });
+                }
+            });
+        }));
+        group.bench_with_input(BenchmarkId::new("item_locks", threads), &threads, |b, &threads| b.iter(|| {
+            thread::scope(|s| {
+                for t in 0..threads {
+                    let (per_item, orders) = (&per_item, &orders);
+                    s.spawn(move || {
+                        for i in 0..OPS_PER_THREAD {
+                            black_box(per_item.check_inventory_stock(&orders[(t * 7919 + i * 31) % orders.len()]));
+                        }
+                    });
+                }
+            });
+        }));
+    }
+    group.finish();
+
+    // Updates from many consumer threads while a reporter keeps taking consistent snapshots
+    let table = wms::inventory_tables::ItemTable::new(per_item.inventory_snapshot());
+    let mut group = c.benchmark_group("inventory_update_contention");
+    group.sample_size(10);
+    for threads in CONTENTION_THREADS {
+        group.throughput(Throughput::Elements((threads * OPS_PER_THREAD) as u64));
+        group.bench_with_input(BenchmarkId::new("item_locks_with_snapshots", threads), &threads, |b, &threads| b.iter(|| {
+            let done = std::sync::atomic::AtomicBool::new(false);
+            thread::scope(|s| {
+                s.spawn(|| while !done.load(std::sync::atomic::Ordering::Relaxed) {
+                    black_box(table.snapshot());
+                });
+                let workers: Vec<_> = (0..threads).map(|t| {
+                    let (table, orders) = (&table, &orders);
+                    s.spawn(move || {
+                        let (order_tx, _order_rx) = channel();
+                        for i in 0..OPS_PER_THREAD {
+                            let order = &orders[(t * 7919 + i * 31) % orders.len()..][..1];
+                            black_box(wms::inventory_management::InventoryManagement::update_inventory(table, order, &order_tx));
+                        }
+                    })
+                }).collect();
+                for worker in workers {
+                    worker.join().unwrap();
+                }
+                done.store(true, std::sync::atomic::Ordering::Relaxed);
+            });
+        }));
+    }
+    group.finish();
+}
+
+// Orders/second published with confirms, one publish per batch versus one per order. Needs a broker with test_queue.
+fn publish_batch_benchmark(c: &mut Criterion) {
+    let codec = wms::codec::Codec::Json;
+    let mut group = c.benchmark_group("publish");
+    group.sample_size(10);
+    for size in BATCH_SIZES {
+        let bodies: Vec<Vec<u8>> = batch_orders(size).iter().map(|order| codec.encode(order).unwrap()).collect();
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::new("send_message", size), &bodies, |b, bodies| b.iter(|| {
+            for body in bodies {
+                wms::rabbitmq::send_message("test_queue", body, codec.content_type()).unwrap();
+            }
+        }));
+        group.bench_with_input(BenchmarkId::new("send_batch", size), &bodies, |b, bodies| b.iter(|| {
+            wms::rabbitmq::send_batch("test_queue", bodies, codec.content_type()).unwrap();
+        }));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, order_generation_benchmark, inventory_check_benchmark, message_sending_benchmark, message_receiving_benchmark, codec_benchmark,
+                    inventory_batch_benchmark, publish_batch_benchmark, contention_benchmark);
 criterion_main!(benches);
 
 
@@ -1,63 +1,226 @@
 use std::thread;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicI32, Ordering};
 use std::time::Duration;
-use rand::Rng;
-use crate::inventory_management::InventoryManagement;
-use crate::rabbitmq::send_message;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::stock_rpc::StockService;
+use crate::rabbitmq::{send_batch, send_message};
+use crate::async_rabbitmq::{self, runtime};
+use crate::catalog::{catalog, Uom};
+use crate::codec;
+use crate::pick::Pick;
+use crate::outbox::outbox;
+use crate::latency::{Stage, Timestamps};
+use crate::metrics::metrics;
+use crate::order_registry::{registry, OrderStatus};
+use crate::events::{bus, Event};
 use serde::{Serialize, Deserialize};
-use serde_json;
+use ulid::{Generator, Ulid};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Order {
-    pub index: i32,
+    pub id: String,     // ULID: unique across generators, the API and restarts; sorts by creation time, and in creation order within a process
+    pub index: i32,     // Display number, only unique within one process
     pub code: String,
     pub quantity: i32,
+    #[serde(default)]
+    pub uom: Uom,       // Unit of quantity; intake converts accepted orders to eaches
     pub order_type: String, // "supply" or "offload"
+    #[serde(default)]
+    pub timestamps: Timestamps,
+    #[serde(default)]
+    pub picks: Vec<Pick>,   // Where an accepted offload is to be picked from
 }
 
+// Shared by the generator and the API so display numbers never repeat within a process
+static NEXT_INDEX: AtomicI32 = AtomicI32::new(1);
+
+// Ids made in the same millisecond count up from the previous one instead of being random
+static ORDER_IDS: Mutex<Generator> = Mutex::new(Generator::new());
+
 impl Order {
-    pub fn start(inventory_management: Arc<InventoryManagement>,) {
-        thread::spawn(move || {
-            let mut index = 0;
-            loop {                              
-                let item_codes = vec!["001", "002", "003"]; // Start to generate random orders 
-                let mut rng = rand::thread_rng();
-                let code = item_codes[rng.gen_range(0..item_codes.len())].to_string();
-                let quantity = rng.gen_range(100..500);
-                let order_type = if rng.gen_bool(0.5) { "supply".to_string() } else { "offload".to_string() }; // 50% chance
-                index += 1;
-
-                let order = Order {
-                    index,
-                    code,
-                    quantity,
-                    order_type,
-                };
-                println!();
-                
-                match order.order_type.as_str() {   // Distribute orders accordingly
-                    "supply" => {
-                        println!("(🎁 Supply Received): {:?}", order);
-                        //Send order to GTS to transport the goods via RMQ
-                        let s_order = serde_json::to_string(&order).expect("Failed to serialize order");
-                        if let Err(e) = send_message("order_queue", &s_order) {
-                            eprintln!("Failed to send message: {}", e);
-                        }
-                    }
-                    "offload" => {
-                        println!("(🚛 Offload Requested): {:?}", order);
-                        if inventory_management.check_inventory_stock(&order) { // Check Inventory First
-                            // Send order to GTS to transport the goods via RMQ
-                            let s_order = serde_json::to_string(&order).expect("Failed to serialize order");
-                            if let Err(e) = send_message("order_queue", &s_order) {
-                                eprintln!("Failed to send message: {}", e);
-                            }
-                        }
-                    }
-                    _ => println!("Unknown order type: {:?}", order), // Error handling
+    pub fn new(code: String, quantity: i32, order_type: String) -> Self {
+        Order::with_uom(code, quantity, Uom::Each, order_type)
+    }
+
+    // An order for cases or pallets; converted to eaches when accepted
+    pub fn with_uom(code: String, quantity: i32, uom: Uom, order_type: String) -> Self {
+        let mut order = Order {
+            id: ORDER_IDS.lock().unwrap().generate().unwrap_or_else(|_| Ulid::new()).to_string(),
+            index: NEXT_INDEX.fetch_add(1, Ordering::SeqCst),
+            code,
+            quantity,
+            uom,
+            order_type,
+            timestamps: Timestamps::default(),
+            picks: Vec::new(),
+        };
+        order.mark(Stage::Created);
+        metrics().inc("wms_orders_generated_total", &[("order_type", &order.order_type)]);
+        registry().register(&order);
+        bus().publish(Event::OrderCreated {
+            order: order.id.clone(), code: order.code.clone(), quantity: order.quantity, order_type: order.order_type.clone(),
+        });
+        order
+    }
+
+    pub fn mark(&mut self, stage: Stage) {
+        self.timestamps.mark(stage);
+    }
+
+    // Validate the order and send it to GTS, returning the resulting status
+    pub fn submit(mut self, stock: &dyn StockService, queue: &str) -> OrderStatus {
+        if let Err(status) = self.accept(stock) {
+            return status;
+        }
+
+        // Send order to GTS to transport the goods via RMQ
+        let codec = codec::for_queue(queue);
+        let body = codec.encode(&self).expect("Failed to encode order");
+        if let Err(e) = send_message(queue, &body, codec.content_type()) {
+            // The order is accepted; keep it locally until the broker confirms it
+            metrics().inc("wms_message_publish_failures_total", &[("queue", queue)]);
+            warn!("order_processing", order = self.id, "Failed to send message, queued in outbox: {}", e);
+            outbox().push(queue, &body, codec.content_type());
+        }
+        self.dispatched()
+    }
+
+    // As submit for many orders at once, publishing the accepted ones in one confirmed batch.
+    // Statuses are returned in the order given.
+    pub fn submit_batch(orders: Vec<Order>, stock: &dyn StockService, queue: &str) -> Vec<OrderStatus> {
+        let results: Vec<Result<Order, OrderStatus>> = orders.into_iter()
+            .map(|mut order| order.accept(stock).map(|_| order))
+            .collect();
+        let accepted: Vec<&Order> = results.iter().filter_map(|r| r.as_ref().ok()).collect();
+        if !accepted.is_empty() {
+            let codec = codec::for_queue(queue);
+            let bodies: Vec<Vec<u8>> = accepted.iter().map(|order| codec.encode(order).expect("Failed to encode order")).collect();
+            if let Err(e) = send_batch(queue, &bodies, codec.content_type()) {
+                metrics().inc("wms_message_publish_failures_total", &[("queue", queue)]);
+                warn!("order_processing", "Failed to send batch of {} orders, queued in outbox: {}", bodies.len(), e);
+                for body in &bodies {
+                    outbox().push(queue, body, codec.content_type());
+                }
+            }
+        }
+        results.into_iter().map(|result| match result {
+            Ok(order) => order.dispatched(),
+            Err(status) => status,
+        }).collect()
+    }
+
+    // Validate the order and reserve its stock, ready to publish; the failing status otherwise
+    fn accept(&mut self, stock: &dyn StockService) -> Result<(), OrderStatus> {
+        match self.order_type.as_str() {   // Distribute orders accordingly
+            "supply" => {
+                info!("order_processing", order = self.id, "Supply received: {} boxes of item {}", self.quantity, self.code);
+            }
+            "offload" => {
+                info!("order_processing", order = self.id, "Offload requested: {} boxes of item {}", self.quantity, self.code);
+            }
+            _ => {
+                warn!("order_processing", order = self.id, "Unknown order type: {}", self.order_type); // Error handling
+                return Err(self.decline());
+            }
+        }
+        match catalog().to_eaches(&self.code, self.quantity, self.uom) {
+            Ok(eaches) => {
+                self.quantity = eaches;
+                self.uom = Uom::Each;
+            }
+            Err(e) => {
+                warn!("order_processing", order = self.id, "Order declined: {}", e);
+                return Err(self.decline());
+            }
+        }
+        match stock.reserve(self) { // Check Inventory First, holding the stock for offloads
+            Ok(true) => {}
+            Ok(false) => return Err(self.decline()),
+            Err(e) => {
+                error!("order_processing", order = self.id, "Stock check failed: {}", e);
+                // Inventory may have reserved the stock before the reply was lost
+                if let Err(e) = stock.release(&self.id) {
+                    warn!("order_processing", order = self.id, "Failed to release stock: {}", e);
                 }
+                registry().set_status(self, OrderStatus::Failed);
+                return Err(OrderStatus::Failed);
+            }
+        }
+        if !self.picks.is_empty() {
+            registry().set_picks(&self.id, &self.picks);
+        }
+        self.mark(Stage::Dispatched);
+        Ok(())
+    }
+
+    fn dispatched(&self) -> OrderStatus {
+        registry().set_status(self, OrderStatus::Dispatched);
+        OrderStatus::Dispatched
+    }
+
+    fn decline(&self) -> OrderStatus {
+        metrics().inc("wms_orders_declined_total", &[("order_type", &self.order_type)]);
+        registry().set_status(self, OrderStatus::Declined);
+        bus().publish(Event::OrderDeclined {
+            order: self.id.clone(), code: self.code.clone(), quantity: self.quantity, order_type: self.order_type.clone(),
+        });
+        OrderStatus::Declined
+    }
+
+    // As submit, publishing on the async runtime. The stock check may block on an RPC, so it runs
+    // where blocking is allowed.
+    pub async fn submit_async(mut self, stock: &dyn StockService, queue: &str) -> OrderStatus {
+        if let Err(status) = tokio::task::block_in_place(|| self.accept(stock)) {
+            return status;
+        }
+
+        let codec = codec::for_queue(queue);
+        let body = codec.encode(&self).expect("Failed to encode order");
+        if let Err(e) = async_rabbitmq::send_message(queue, &body, codec.content_type()).await {
+            metrics().inc("wms_message_publish_failures_total", &[("queue", queue)]);
+            warn!("order_processing", order = self.id, "Failed to send message, queued in outbox: {}", e);
+            outbox().push(queue, &body, codec.content_type());
+        }
+        self.dispatched()
+    }
+
+    // A seed makes the generated order stream reproducible across runs
+    pub fn start(stock: Arc<dyn StockService>, queue: String, seed: Option<u64>) {
+        thread::spawn(move || {
+            let mut rng = seeded_rng(seed);
+            loop {
+                random_order(&mut rng).submit(stock.as_ref(), &queue);
                 thread::sleep(Duration::from_secs(rng.gen_range(2..5)));    // Simulate random time delay between order generation
             }
         });
     }
+
+    // As start, as a task on the async runtime
+    pub fn start_async(stock: Arc<dyn StockService>, queue: String, seed: Option<u64>) {
+        runtime().spawn(async move {
+            let mut rng = seeded_rng(seed);
+            loop {
+                random_order(&mut rng).submit_async(stock.as_ref(), &queue).await;
+                tokio::time::sleep(Duration::from_secs(rng.gen_range(2..5))).await;
+            }
+        });
+    }
+}
+
+// Reproducible from a seed, random otherwise
+pub fn seeded_rng(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }
+}
+
+fn random_order(rng: &mut StdRng) -> Order {
+    let item_codes: Vec<&str> = catalog().items().map(|item| item.sku.as_str()).collect(); // Start to generate random orders
+    let code = item_codes[rng.gen_range(0..item_codes.len())].to_string();
+    let quantity = rng.gen_range(100..500);
+    let order_type = if rng.gen_bool(0.5) { "supply".to_string() } else { "offload".to_string() }; // 50% chance
+    Order::new(code, quantity, order_type)
 }
@@ -0,0 +1,218 @@
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+use serde::{Serialize, Deserialize};
+use crate::catalog::Uom;
+use crate::events::{bus, Event};
+use crate::latency::{now_micros, Timestamps};
+use crate::order_processing::Order;
+use crate::pick::Pick;
+
+const MAX_TRACKED_ORDERS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderStatus {
+    Created,
+    Declined,
+    Dispatched,
+    InTransit,
+    Delivered,
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+impl OrderStatus {
+    // Statuses only move forward, so a late update from a slower service never undoes a newer one.
+    // Declined, Completed, Cancelled and Failed are final; only orders not yet in transit can be cancelled.
+    pub fn can_become(self, next: OrderStatus) -> bool {
+        use OrderStatus::*;
+        match (self, next) {
+            (Declined | Completed | Cancelled | Failed, _) => false,
+            (Created | Dispatched, Cancelled) => true,
+            (_, Cancelled) => false,
+            (_, Declined | Failed) => true,
+            _ => self.step() < next.step(),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OrderStatus::Created => "created",
+            OrderStatus::Declined => "declined",
+            OrderStatus::Dispatched => "dispatched",
+            OrderStatus::InTransit => "in_transit",
+            OrderStatus::Delivered => "delivered",
+            OrderStatus::Completed => "completed",
+            OrderStatus::Cancelled => "cancelled",
+            OrderStatus::Failed => "failed",
+        }
+    }
+
+    // Position along the normal course of an order
+    fn step(self) -> u8 {
+        match self {
+            OrderStatus::Created => 0,
+            OrderStatus::Dispatched => 1,
+            OrderStatus::InTransit => 2,
+            OrderStatus::Delivered => 3,
+            _ => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OrderRecord {
+    pub order: Order,
+    pub status: OrderStatus,
+    pub updated_at_us: u64,
+}
+
+// Latest known status of recent orders, for the API and the transport controller.
+// Keyed by order id, which sorts by creation time, so the first entry is always the oldest.
+// Each service publishes the changes it makes as events and applies those of the others, so
+// every process sees orders move on, including cancellations accepted by order intake.
+pub struct OrderRegistry {
+    orders: Mutex<BTreeMap<String, OrderRecord>>,
+}
+
+impl OrderRegistry {
+    fn new() -> Self {
+        OrderRegistry { orders: Mutex::new(BTreeMap::new()) }
+    }
+
+    pub fn register(&self, order: &Order) {
+        let mut orders = self.orders.lock().unwrap();
+        insert(&mut orders, OrderRecord { order: order.clone(), status: OrderStatus::Created, updated_at_us: now_micros() });
+    }
+
+    // Move an order on and tell the other services; updates that would take it back are ignored
+    pub fn set_status(&self, order: &Order, status: OrderStatus) {
+        if self.update(order, status) {
+            publish_status(order, status);
+        }
+    }
+
+    // Apply a status change published by another service, or echoed back from this one
+    pub fn apply_remote(&self, event: &Event) {
+        if let Event::OrderStatusChanged { order, code, quantity, order_type, status } = event {
+            let order = Order {
+                id: order.clone(), index: 0, code: code.clone(), quantity: *quantity, uom: Uom::Each,
+                order_type: order_type.clone(), timestamps: Timestamps::default(), picks: Vec::new(),
+            };
+            self.update(&order, *status);
+        }
+    }
+
+    // Apply a status change another service made and publishes itself
+    pub fn record(&self, order: &Order, status: OrderStatus) {
+        self.update(order, status);
+    }
+
+    // Whether the status changed; orders this process has not seen yet are added
+    fn update(&self, order: &Order, status: OrderStatus) -> bool {
+        let mut orders = self.orders.lock().unwrap();
+        match orders.get_mut(&order.id) {
+            Some(record) if record.status.can_become(status) => {
+                record.status = status;
+                record.updated_at_us = now_micros();
+                true
+            }
+            Some(_) => false,
+            None => {
+                insert(&mut orders, OrderRecord { order: order.clone(), status, updated_at_us: now_micros() });
+                true
+            }
+        }
+    }
+
+    // Record where an accepted offload will be picked from
+    pub fn set_picks(&self, id: &str, picks: &[Pick]) {
+        if let Some(record) = self.orders.lock().unwrap().get_mut(id) {
+            record.order.picks = picks.to_vec();
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<OrderRecord> {
+        self.orders.lock().unwrap().get(id).cloned()
+    }
+
+    // Look an order up by id, or by its display number as typed by a person
+    pub fn find(&self, key: &str) -> Option<OrderRecord> {
+        let orders = self.orders.lock().unwrap();
+        match (orders.get(key), key.trim_start_matches('#').parse::<i32>()) {
+            (Some(record), _) => Some(record.clone()),
+            (None, Ok(index)) => orders.values().rev().find(|r| r.order.index == index).cloned(),
+            (None, Err(_)) => None,
+        }
+    }
+
+    // Most recent orders currently in the given status, newest first
+    pub fn recent(&self, status: OrderStatus, limit: usize) -> Vec<OrderRecord> {
+        self.orders.lock().unwrap().values().rev().filter(|r| r.status == status).take(limit).cloned().collect()
+    }
+
+    pub fn is_cancelled(&self, id: &str) -> bool {
+        self.get(id).map(|r| r.status == OrderStatus::Cancelled).unwrap_or(false)
+    }
+
+    // Claim the order for a forklift trip: a created or dispatched order moves to in transit, while one
+    // in transit or delivered is being redelivered and may go again. False if the order was cancelled or
+    // is otherwise finished; checked and changed under one lock so a cancellation cannot slip in between.
+    pub fn start_trip(&self, order: &Order) -> bool {
+        let mut orders = self.orders.lock().unwrap();
+        match orders.get_mut(&order.id) {
+            Some(record) => match record.status {
+                OrderStatus::InTransit | OrderStatus::Delivered => return true,
+                status if !status.can_become(OrderStatus::InTransit) => return false,
+                _ => {
+                    record.status = OrderStatus::InTransit;
+                    record.updated_at_us = now_micros();
+                }
+            },
+            None => insert(&mut orders, OrderRecord { order: order.clone(), status: OrderStatus::InTransit, updated_at_us: now_micros() }),
+        }
+        drop(orders);
+        publish_status(order, OrderStatus::InTransit);
+        true
+    }
+
+    // Orders that will never reach inventory, so any stock held for them can be freed
+    pub fn is_abandoned(&self, id: &str) -> bool {
+        self.get(id).map(|r| matches!(r.status, OrderStatus::Failed | OrderStatus::Cancelled)).unwrap_or(false)
+    }
+
+    // Only orders not yet picked up by a forklift can be cancelled; transport hears of it as an event
+    pub fn cancel(&self, id: &str) -> Result<OrderRecord, String> {
+        let record = {
+            let mut orders = self.orders.lock().unwrap();
+            let record = orders.get_mut(id).ok_or_else(|| format!("Order {} not found", id))?;
+            if !record.status.can_become(OrderStatus::Cancelled) {
+                return Err(format!("Order {} cannot be cancelled in status {:?}", id, record.status));
+            }
+            record.status = OrderStatus::Cancelled;
+            record.updated_at_us = now_micros();
+            record.clone()
+        };
+        publish_status(&record.order, OrderStatus::Cancelled);
+        Ok(record)
+    }
+}
+
+fn insert(orders: &mut BTreeMap<String, OrderRecord>, record: OrderRecord) {
+    orders.insert(record.order.id.clone(), record);
+    while orders.len() > MAX_TRACKED_ORDERS {
+        orders.pop_first(); // Forget the oldest orders
+    }
+}
+
+fn publish_status(order: &Order, status: OrderStatus) {
+    bus().publish(Event::OrderStatusChanged {
+        order: order.id.clone(), code: order.code.clone(), quantity: order.quantity, order_type: order.order_type.clone(), status,
+    });
+}
+
+pub fn registry() -> &'static OrderRegistry {
+    static REGISTRY: OnceLock<OrderRegistry> = OnceLock::new();
+    REGISTRY.get_or_init(OrderRegistry::new)
+}
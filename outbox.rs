@@ -0,0 +1,170 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+use serde::{Serialize, Deserialize, Deserializer};
+use crate::latency::now_micros;
+use crate::metrics::metrics;
+use crate::rabbitmq::{retry_policy, try_send_message};
+
+// How often the outbox is checked when it is empty or the last flush succeeded
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+// A message the broker has not confirmed yet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    pub queue: String,
+    #[serde(deserialize_with = "body_bytes")]
+    pub body: Vec<u8>,
+    #[serde(default = "json_content_type")]
+    pub content_type: String,   // Outboxes written before codecs hold JSON text bodies without one
+    pub attempts: u32,
+    pub queued_at_us: u64,
+}
+
+// Bytes, or the JSON text an older outbox file stored
+fn body_bytes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Body {
+        Bytes(Vec<u8>),
+        Text(String),
+    }
+    Ok(match Body::deserialize(deserializer)? {
+        Body::Bytes(bytes) => bytes,
+        Body::Text(text) => text.into_bytes(),
+    })
+}
+
+fn json_content_type() -> String {
+    "application/json".to_string()
+}
+
+// Messages whose publish could not be confirmed, kept locally and retried in order until the broker
+// accepts them. With a file configured the outbox survives a restart.
+pub struct Outbox {
+    path: Option<String>,
+    entries: Mutex<VecDeque<OutboxEntry>>,
+}
+
+static OUTBOX: OnceLock<Outbox> = OnceLock::new();
+
+pub fn outbox() -> &'static Outbox {
+    OUTBOX.get_or_init(|| Outbox { path: None, entries: Mutex::new(VecDeque::new()) })
+}
+
+// Load what a previous run left behind and retry it in the background. Must run before anything is
+// pushed, or the outbox would already be in use without its file.
+pub fn start(path: Option<String>) -> Result<(), String> {
+    let entries = path.as_deref().map(load).unwrap_or_default();
+    let loaded = entries.len();
+    OUTBOX.set(Outbox { path, entries: Mutex::new(entries) }).map_err(|_| "Outbox configured after it was first used".to_string())?;
+    if loaded > 0 {
+        info!("outbox", "Loaded {} unconfirmed message(s) from a previous run", loaded);
+    }
+    metrics().set("wms_outbox_depth", &[], loaded as f64);
+    let outbox = outbox();
+
+    thread::spawn(move || {
+        let mut failures = 0;
+        loop {
+            if outbox.flush() {
+                failures = 0;
+                thread::sleep(FLUSH_INTERVAL);
+            } else {
+                failures += 1;
+                thread::sleep(retry_policy().backoff(failures));
+            }
+        }
+    });
+    Ok(())
+}
+
+// A file that cannot be read is moved aside rather than overwritten, so its messages are not lost
+fn load(path: &str) -> VecDeque<OutboxEntry> {
+    let Ok(contents) = fs::read_to_string(path) else { return VecDeque::new() };
+    match serde_json::from_str(&contents) {
+        Ok(entries) => entries,
+        Err(e) => {
+            let aside = format!("{}.unreadable", path);
+            match fs::rename(path, &aside) {
+                Ok(()) => error!("outbox", "Cannot read outbox {}, moved it to {}: {}", path, aside, e),
+                Err(rename_error) => error!("outbox", "Cannot read outbox {} ({}) nor move it aside: {}", path, e, rename_error),
+            }
+            VecDeque::new()
+        }
+    }
+}
+
+impl Outbox {
+    pub fn push(&self, queue: &str, body: &[u8], content_type: &str) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.push_back(OutboxEntry {
+            queue: queue.to_string(), body: body.to_vec(), content_type: content_type.to_string(), attempts: 0, queued_at_us: now_micros(),
+        });
+        self.save(&entries);
+        metrics().set("wms_outbox_depth", &[], entries.len() as f64);
+    }
+
+    // Publish queued messages oldest first; stops at the first failure so order is kept. False if one failed.
+    fn flush(&self) -> bool {
+        loop {
+            let entry = match self.entries.lock().unwrap().front() {
+                Some(entry) => entry.clone(),
+                None => return true,
+            };
+            let result = try_send_message(&entry.queue, &entry.body, &entry.content_type);
+            let mut entries = self.entries.lock().unwrap();
+            match &result {
+                Ok(()) => {
+                    entries.pop_front();
+                    info!("outbox", "Published queued message to {} after {} attempt(s)", entry.queue, entry.attempts + 1);
+                }
+                Err(e) => {
+                    if let Some(front) = entries.front_mut() {
+                        front.attempts += 1;
+                    }
+                    warn!("outbox", "Retry of queued message to {} failed: {}", entry.queue, e);
+                }
+            }
+            self.save(&entries);
+            metrics().set("wms_outbox_depth", &[], entries.len() as f64);
+            if result.is_err() {
+                return false;
+            }
+        }
+    }
+
+    fn save(&self, entries: &VecDeque<OutboxEntry>) {
+        if let Some(path) = &self.path {
+            // Replace the file in one step, so a crash mid-write leaves the previous outbox in place
+            let json = serde_json::to_string(entries).expect("Failed to serialize outbox");
+            let temp = format!("{}.tmp", path);
+            if let Err(e) = fs::write(&temp, json).and_then(|_| fs::rename(&temp, path)) {
+                error!("outbox", "Failed to save outbox to {}: {}", path, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_from_before_codecs_are_read_as_json() {
+        let json = r#"[{"queue":"order_queue","body":"{\"index\":1}","attempts":2,"queued_at_us":5}]"#;
+        let entries: VecDeque<OutboxEntry> = serde_json::from_str(json).unwrap();
+        assert_eq!(entries[0].body, br#"{"index":1}"#);
+        assert_eq!(entries[0].content_type, "application/json");
+    }
+
+    #[test]
+    fn entries_roundtrip() {
+        let entry = OutboxEntry { queue: "q".to_string(), body: vec![0x82, 0xa1], content_type: "application/msgpack".to_string(), attempts: 0, queued_at_us: 1 };
+        let entries: Vec<OutboxEntry> = serde_json::from_str(&serde_json::to_string(&[entry]).unwrap()).unwrap();
+        assert_eq!(entries[0].body, vec![0x82, 0xa1]);
+        assert_eq!(entries[0].content_type, "application/msgpack");
+    }
+}
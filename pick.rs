@@ -0,0 +1,315 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BTreeMap;
+use std::sync::{MutexGuard, OnceLock};
+use serde::{Serialize, Deserialize};
+use crate::catalog::CatalogItem;
+use crate::inventory_management::{Location, Lot, Rack};
+use crate::putaway::Placement;
+
+static CATEGORY_PICKS: OnceLock<BTreeMap<String, PickKind>> = OnceLock::new();
+
+// One line of an offload's pick list: how many to take from which lot in which bin
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Pick {
+    pub bin: String,
+    pub quantity: i32,
+    pub received_at_us: u64,
+    pub expires_at_us: Option<u64>,
+}
+
+// A lot by the bin it is in and its index there
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LotRef {
+    pub location: Location,
+    pub lot: usize,
+}
+
+impl LotRef {
+    fn get<'a>(&self, racks: &'a [MutexGuard<Rack>]) -> &'a Lot {
+        &racks[self.location.rack].bin(self.location).lots[self.lot]
+    }
+}
+
+// Decides which lots an offload is picked from. `plan` passes every usable lot of the item and takes
+// from them in the order the strategy leaves them.
+pub trait PickStrategy: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn rank(&self, racks: &[MutexGuard<Rack>], candidates: &mut [LotRef]);
+
+    // Whether the lot may be picked at the given time
+    fn usable(&self, _lot: &Lot, _now_us: u64) -> bool {
+        true
+    }
+}
+
+// Oldest receipt first
+pub struct Fifo;
+
+impl PickStrategy for Fifo {
+    fn name(&self) -> &'static str {
+        "fifo"
+    }
+
+    fn rank(&self, racks: &[MutexGuard<Rack>], candidates: &mut [LotRef]) {
+        candidates.sort_by_key(|lot| lot.get(racks).received_at_us);
+    }
+}
+
+// Soonest expiry first, then oldest receipt; lots that never expire go last and expired lots are left
+pub struct Fefo;
+
+impl PickStrategy for Fefo {
+    fn name(&self) -> &'static str {
+        "fefo"
+    }
+
+    fn usable(&self, lot: &Lot, now_us: u64) -> bool {
+        lot.expires_at_us.is_none_or(|expires_at_us| expires_at_us > now_us)
+    }
+
+    fn rank(&self, racks: &[MutexGuard<Rack>], candidates: &mut [LotRef]) {
+        candidates.sort_by_key(|lot| {
+            let lot = lot.get(racks);
+            (lot.expires_at_us.unwrap_or(u64::MAX), lot.received_at_us)
+        });
+    }
+}
+
+// Newest receipt first
+pub struct Lifo;
+
+impl PickStrategy for Lifo {
+    fn name(&self) -> &'static str {
+        "lifo"
+    }
+
+    fn rank(&self, racks: &[MutexGuard<Rack>], candidates: &mut [LotRef]) {
+        candidates.sort_by_key(|lot| Reverse(lot.get(racks).received_at_us));
+    }
+}
+
+// Largest lots first, so the offload is picked from as few bins as possible
+pub struct FewestLocations;
+
+impl PickStrategy for FewestLocations {
+    fn name(&self) -> &'static str {
+        "fewest_locations"
+    }
+
+    fn rank(&self, racks: &[MutexGuard<Rack>], candidates: &mut [LotRef]) {
+        candidates.sort_by_key(|lot| Reverse(lot.get(racks).quantity));
+    }
+}
+
+// Shortest trip to the shipping door, oldest receipt first among equals
+pub struct ClosestToShipping;
+
+impl PickStrategy for ClosestToShipping {
+    fn name(&self) -> &'static str {
+        "closest_to_shipping"
+    }
+
+    fn rank(&self, racks: &[MutexGuard<Rack>], candidates: &mut [LotRef]) {
+        let distance = |lot: &LotRef| racks[lot.location.rack].shipping_distance_m(lot.location);
+        candidates.sort_by(|a, b| {
+            distance(a).partial_cmp(&distance(b)).unwrap_or(Ordering::Equal)
+                .then_with(|| a.get(racks).received_at_us.cmp(&b.get(racks).received_at_us))
+        });
+    }
+}
+
+// Strategy chosen per item category in the config
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PickKind {
+    #[default]
+    Fifo,
+    Fefo,
+    Lifo,
+    FewestLocations,
+    ClosestToShipping,
+}
+
+impl PickKind {
+    pub fn strategy(self) -> Box<dyn PickStrategy> {
+        match self {
+            PickKind::Fifo => Box::new(Fifo),
+            PickKind::Fefo => Box::new(Fefo),
+            PickKind::Lifo => Box::new(Lifo),
+            PickKind::FewestLocations => Box::new(FewestLocations),
+            PickKind::ClosestToShipping => Box::new(ClosestToShipping),
+        }
+    }
+}
+
+// Set once at startup from the config, before anything is picked: strategy by item category, FIFO for the rest
+pub fn configure(category_picks: BTreeMap<String, PickKind>) -> Result<(), String> {
+    CATEGORY_PICKS.set(category_picks).map_err(|_| "Pick strategies configured after they were first used".to_string())
+}
+
+pub fn for_category(category: &str) -> PickKind {
+    CATEGORY_PICKS.get_or_init(BTreeMap::new).get(category).copied().unwrap_or_default()
+}
+
+// The pick list for an offload: the usable lots the strategy ranks first, until the quantity is covered.
+// Boxes `promised` to other offloads are left to them. Nothing is taken; a shorter list means there is
+// not that much free in the racks.
+pub fn plan(racks: &[MutexGuard<Rack>], strategy: &dyn PickStrategy, item: &CatalogItem, promised: &[Pick], quantity: i32, now_us: u64) -> Vec<Pick> {
+    let mut candidates = Vec::new();
+    for (r, rack) in racks.iter().enumerate() {
+        for (l, level) in rack.levels.iter().enumerate() {
+            for (b, bin) in level.bins.iter().enumerate() {
+                for (i, lot) in bin.lots.iter().enumerate() {
+                    if lot.sku == item.sku && strategy.usable(lot, now_us) {
+                        candidates.push(LotRef { location: Location { rack: r, level: l, bin: b }, lot: i });
+                    }
+                }
+            }
+        }
+    }
+    strategy.rank(racks, &mut candidates);
+
+    let mut picks = Vec::new();
+    let mut remaining = quantity;
+    for candidate in candidates {
+        if remaining <= 0 {
+            break;
+        }
+        let lot = candidate.get(racks);
+        let bin = &racks[candidate.location.rack].bin(candidate.location).name;
+        let taken: i32 = promised.iter()
+            .filter(|pick| &pick.bin == bin && pick.received_at_us == lot.received_at_us)
+            .map(|pick| pick.quantity)
+            .sum();
+        let quantity = (lot.quantity - taken).min(remaining);
+        if quantity <= 0 {
+            continue;
+        }
+        picks.push(Pick { bin: bin.clone(), quantity, received_at_us: lot.received_at_us, expires_at_us: lot.expires_at_us });
+        remaining -= quantity;
+    }
+    picks
+}
+
+// Take the boxes on the pick list. Lots picked by someone else since the list was made leave a
+// shortfall, which is picked afresh with the strategy, around what is promised to other offloads.
+// What was taken from where.
+pub fn execute(racks: &mut [MutexGuard<Rack>], strategy: &dyn PickStrategy, item: &CatalogItem, picks: &[Pick], promised: &[Pick],
+                quantity: i32, now_us: u64) -> Vec<Placement> {
+    let mut taken_from = Vec::new();
+    let mut remaining = quantity;
+    take(racks, item, picks, &mut remaining, &mut taken_from);
+    if remaining > 0 {
+        let picks = plan(racks, strategy, item, promised, remaining, now_us);
+        take(racks, item, &picks, &mut remaining, &mut taken_from);
+    }
+    taken_from
+}
+
+fn take(racks: &mut [MutexGuard<Rack>], item: &CatalogItem, picks: &[Pick], remaining: &mut i32, taken_from: &mut Vec<Placement>) {
+    for pick in picks {
+        if *remaining <= 0 {
+            return;
+        }
+        let Some(location) = find_bin(racks, &pick.bin) else { continue };
+        let rack = &mut *racks[location.rack];
+        let taken = rack.bin_mut(location).take(item, pick.received_at_us, pick.quantity.min(*remaining));
+        if taken > 0 {
+            rack.capacity -= taken;
+            *remaining -= taken;
+            taken_from.push(Placement { location, quantity: taken });
+        }
+    }
+}
+
+fn find_bin(racks: &[MutexGuard<Rack>], name: &str) -> Option<Location> {
+    racks.iter().enumerate().find_map(|(r, rack)| {
+        rack.levels.iter().enumerate().find_map(|(l, level)| {
+            level.bins.iter().position(|bin| bin.name == name).map(|b| Location { rack: r, level: l, bin: b })
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::{Dimensions, StorageRequirements};
+    use crate::inventory_tables::RackTable;
+
+    // Racks A and B of one level of three 1 m bins; A is near shipping, B near receiving. Three lots:
+    // B L1 B1 holds 3 received at 100 that never expire, B L1 B2 6 received at 200 expiring at 2000,
+    // and A L1 B3 2 received at 300 expiring at 1000.
+    fn racks() -> RackTable {
+        let bin = Dimensions { length_mm: 1000, width_mm: 1000, height_mm: 1000 };
+        let mut a = Rack::new("A", 1, 3, bin.clone(), 100.0);
+        let mut b = Rack::new("B", 1, 3, bin, 100.0);
+        (a.receiving_m, a.shipping_m) = (20.0, 5.0);
+        (b.receiving_m, b.shipping_m) = (5.0, 20.0);
+        let lot = |quantity, received_at_us, expires_at_us| Lot { sku: "001".to_string(), quantity, received_at_us, expires_at_us };
+        b.bin_mut(at(1, 0)).lots.push(lot(3, 100, None));
+        b.bin_mut(at(1, 1)).lots.push(lot(6, 200, Some(2000)));
+        a.bin_mut(at(0, 2)).lots.push(lot(2, 300, Some(1000)));
+        RackTable::new(vec![a, b])
+    }
+
+    fn item() -> CatalogItem {
+        CatalogItem {
+            sku: "001".to_string(),
+            description: "Box".to_string(),
+            category: String::new(),
+            packs: Default::default(),
+            dimensions: Dimensions { length_mm: 500, width_mm: 500, height_mm: 500 },
+            weight_kg: 10.0,
+            storage: StorageRequirements::default(),
+            shelf_life_days: None,
+        }
+    }
+
+    fn at(rack: usize, bin: usize) -> Location {
+        Location { rack, level: 0, bin }
+    }
+
+    fn pick(bin: &str, quantity: i32, received_at_us: u64, expires_at_us: Option<u64>) -> Pick {
+        Pick { bin: bin.to_string(), quantity, received_at_us, expires_at_us }
+    }
+
+    fn picks(kind: PickKind, promised: &[Pick], quantity: i32, now_us: u64) -> Vec<Pick> {
+        racks().with_racks(|racks| plan(racks, kind.strategy().as_ref(), &item(), promised, quantity, now_us))
+    }
+
+    #[test]
+    fn fifo_picks_the_oldest_lots_first() {
+        assert_eq!(picks(PickKind::Fifo, &[], 4, 0), vec![pick("B L1 B1", 3, 100, None), pick("B L1 B2", 1, 200, Some(2000))]);
+    }
+
+    #[test]
+    fn fefo_picks_the_soonest_expiring_lots_first() {
+        assert_eq!(picks(PickKind::Fefo, &[], 4, 0), vec![pick("A L1 B3", 2, 300, Some(1000)), pick("B L1 B2", 2, 200, Some(2000))]);
+    }
+
+    #[test]
+    fn fefo_leaves_expired_lots() {
+        assert_eq!(picks(PickKind::Fefo, &[], 10, 1500), vec![pick("B L1 B2", 6, 200, Some(2000)), pick("B L1 B1", 3, 100, None)]);
+    }
+
+    #[test]
+    fn lifo_picks_the_newest_lots_first() {
+        assert_eq!(picks(PickKind::Lifo, &[], 4, 0), vec![pick("A L1 B3", 2, 300, Some(1000)), pick("B L1 B2", 2, 200, Some(2000))]);
+    }
+
+    #[test]
+    fn fewest_locations_picks_the_largest_lot_first() {
+        assert_eq!(picks(PickKind::FewestLocations, &[], 4, 0), vec![pick("B L1 B2", 4, 200, Some(2000))]);
+    }
+
+    #[test]
+    fn closest_to_shipping_starts_at_the_nearest_rack() {
+        assert_eq!(picks(PickKind::ClosestToShipping, &[], 4, 0), vec![pick("A L1 B3", 2, 300, Some(1000)), pick("B L1 B1", 2, 100, None)]);
+    }
+
+    #[test]
+    fn plan_leaves_lots_promised_to_other_offloads() {
+        let promised = [pick("B L1 B1", 2, 100, None)];
+        assert_eq!(picks(PickKind::Fifo, &promised, 4, 0), vec![pick("B L1 B1", 1, 100, None), pick("B L1 B2", 3, 200, Some(2000))]);
+    }
+}
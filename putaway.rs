@@ -0,0 +1,413 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::MutexGuard;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use serde::{Serialize, Deserialize};
+use crate::catalog::{catalog, CatalogItem};
+use crate::inventory_management::{self, Item, Location, Rack};
+use crate::inventory_tables::RackTable;
+use crate::pick;
+
+// Part of a supply going into, or an offload coming out of, one bin
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placement {
+    pub location: Location,
+    pub quantity: i32,
+}
+
+// Why a supply cannot be put away; nothing of it is placed
+#[derive(Debug)]
+pub enum PutawayError {
+    Unsuitable(String),                     // No rack meets its storage requirements, or no bin opening fits it
+    NoSpace { requested: i32, room: i32 },
+}
+
+impl fmt::Display for PutawayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PutawayError::Unsuitable(sku) => write!(f, "no suitable bin for {}", sku),
+            PutawayError::NoSpace { requested, room } => write!(f, "room for {} of {} boxes", room, requested),
+        }
+    }
+}
+
+impl PutawayError {
+    // Metric label
+    pub fn reason(&self) -> &'static str {
+        match self {
+            PutawayError::Unsuitable(_) => "unsuitable",
+            PutawayError::NoSpace { .. } => "no_space",
+        }
+    }
+}
+
+// How fast an item moves: A items make up the first 80% of offloaded boxes, B the next 15%, C the rest
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Velocity {
+    A,
+    B,
+    C,
+}
+
+impl Velocity {
+    pub fn of(sku: &str, items: &[Item]) -> Velocity {
+        let total: i64 = items.iter().map(|item| item.exit as i64).sum();
+        if total == 0 {
+            return Velocity::B;
+        }
+        let mut by_exits: Vec<&Item> = items.iter().collect();
+        by_exits.sort_by(|a, b| b.exit.cmp(&a.exit).then_with(|| a.code.cmp(&b.code)));
+        let mut before = 0i64;     // Offloads of the items moving faster than this one
+        for item in by_exits {
+            if item.code == sku {
+                break;
+            }
+            before += item.exit as i64;
+        }
+        let share = before as f64 / total as f64;
+        if share < 0.8 {
+            Velocity::A
+        } else if share < 0.95 {
+            Velocity::B
+        } else {
+            Velocity::C
+        }
+    }
+}
+
+// Decides which bins a supply goes to. `plan` passes the bins that can take the item at all and fills
+// them in the order the strategy leaves them, each as far as its weight and volume allow.
+pub trait PutawayStrategy: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn rank(&self, racks: &[MutexGuard<Rack>], item: &CatalogItem, velocity: Velocity, candidates: &mut [Location]);
+
+    // Whether `rank` looks at the velocity; working it out takes a snapshot of every item
+    fn uses_velocity(&self) -> bool {
+        false
+    }
+}
+
+// Rack order, A to E, level by level
+pub struct FirstFit;
+
+impl PutawayStrategy for FirstFit {
+    fn name(&self) -> &'static str {
+        "first_fit"
+    }
+
+    fn rank(&self, _racks: &[MutexGuard<Rack>], _item: &CatalogItem, _velocity: Velocity, _candidates: &mut [Location]) {}
+}
+
+// Shortest trip from the receiving door
+pub struct ClosestToReceiving;
+
+impl PutawayStrategy for ClosestToReceiving {
+    fn name(&self) -> &'static str {
+        "closest_to_receiving"
+    }
+
+    fn rank(&self, racks: &[MutexGuard<Rack>], _item: &CatalogItem, _velocity: Velocity, candidates: &mut [Location]) {
+        candidates.sort_by(|a, b| by_distance(racks[a.rack].receiving_distance_m(*a), racks[b.rack].receiving_distance_m(*b)));
+    }
+}
+
+// Top up bins already holding the SKU, fullest first, so it stays in few locations
+pub struct ConsolidateSameSku;
+
+impl PutawayStrategy for ConsolidateSameSku {
+    fn name(&self) -> &'static str {
+        "consolidate_same_sku"
+    }
+
+    fn rank(&self, racks: &[MutexGuard<Rack>], item: &CatalogItem, _velocity: Velocity, candidates: &mut [Location]) {
+        let held = |location: &Location| racks[location.rack].bin(*location).quantity_of(&item.sku);
+        candidates.sort_by_key(|location| -held(location));
+    }
+}
+
+// Fast movers nearest the shipping door, slow movers furthest from it, the rest in between
+pub struct AbcVelocity;
+
+impl PutawayStrategy for AbcVelocity {
+    fn name(&self) -> &'static str {
+        "abc_velocity"
+    }
+
+    fn uses_velocity(&self) -> bool {
+        true
+    }
+
+    fn rank(&self, racks: &[MutexGuard<Rack>], _item: &CatalogItem, velocity: Velocity, candidates: &mut [Location]) {
+        let distance = |location: &Location| racks[location.rack].shipping_distance_m(*location);
+        let mut distances: Vec<f64> = candidates.iter().map(distance).collect();
+        distances.sort_by(|a, b| by_distance(*a, *b));
+        let middle = distances.get(distances.len() / 2).copied().unwrap_or(0.0);
+        candidates.sort_by(|a, b| match velocity {
+            Velocity::A => by_distance(distance(a), distance(b)),
+            Velocity::B => by_distance((distance(a) - middle).abs(), (distance(b) - middle).abs()),
+            Velocity::C => by_distance(distance(b), distance(a)),
+        });
+    }
+}
+
+// Emptiest racks first, and the emptiest bins within them, to even out the load across racks
+pub struct SpreadForBalance;
+
+impl PutawayStrategy for SpreadForBalance {
+    fn name(&self) -> &'static str {
+        "spread_for_balance"
+    }
+
+    fn rank(&self, racks: &[MutexGuard<Rack>], _item: &CatalogItem, _velocity: Velocity, candidates: &mut [Location]) {
+        let fill = |location: &Location| (racks[location.rack].fill_ratio(), racks[location.rack].bin(*location).fill_ratio());
+        candidates.sort_by(|a, b| {
+            let (a, b) = (fill(a), fill(b));
+            by_distance(a.0, b.0).then_with(|| by_distance(a.1, b.1))
+        });
+    }
+}
+
+fn by_distance(a: f64, b: f64) -> Ordering {
+    a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+}
+
+// Strategy chosen in the config
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PutawayKind {
+    #[default]
+    FirstFit,
+    ClosestToReceiving,
+    ConsolidateSameSku,
+    AbcVelocity,
+    SpreadForBalance,
+}
+
+pub const PUTAWAY_KINDS: [PutawayKind; 5] = [
+    PutawayKind::FirstFit,
+    PutawayKind::ClosestToReceiving,
+    PutawayKind::ConsolidateSameSku,
+    PutawayKind::AbcVelocity,
+    PutawayKind::SpreadForBalance,
+];
+
+impl PutawayKind {
+    pub fn strategy(self) -> Box<dyn PutawayStrategy> {
+        match self {
+            PutawayKind::FirstFit => Box::new(FirstFit),
+            PutawayKind::ClosestToReceiving => Box::new(ClosestToReceiving),
+            PutawayKind::ConsolidateSameSku => Box::new(ConsolidateSameSku),
+            PutawayKind::AbcVelocity => Box::new(AbcVelocity),
+            PutawayKind::SpreadForBalance => Box::new(SpreadForBalance),
+        }
+    }
+}
+
+// Fill the bins the strategy ranks first until the whole quantity is placed. Bins are checked against
+// the item's storage requirements, dimensions and weight before anything is placed.
+pub fn plan(racks: &[MutexGuard<Rack>], strategy: &dyn PutawayStrategy, item: &CatalogItem, velocity: Velocity, quantity: i32)
+    -> Result<Vec<Placement>, PutawayError> {
+    let mut candidates = Vec::new();
+    for (r, rack) in racks.iter().enumerate() {
+        if !rack.accepts(&item.storage) {
+            continue;
+        }
+        for (l, level) in rack.levels.iter().enumerate() {
+            for (b, bin) in level.bins.iter().enumerate() {
+                if item.dimensions.fits(&bin.dimensions) {
+                    candidates.push(Location { rack: r, level: l, bin: b });
+                }
+            }
+        }
+    }
+    if candidates.is_empty() {
+        return Err(PutawayError::Unsuitable(item.sku.clone()));
+    }
+    strategy.rank(racks, item, velocity, &mut candidates);
+
+    let mut placements = Vec::new();
+    let mut remaining = quantity;
+    for location in candidates {
+        if remaining <= 0 {
+            break;
+        }
+        let quantity = racks[location.rack].bin(location).room_for(item).min(remaining);
+        if quantity > 0 {
+            placements.push(Placement { location, quantity });
+            remaining -= quantity;
+        }
+    }
+    if remaining > 0 {
+        return Err(PutawayError::NoSpace { requested: quantity, room: quantity - remaining });
+    }
+    Ok(placements)
+}
+
+// Put the planned quantities into their bins as lots received at the given time
+pub fn apply(racks: &mut [MutexGuard<Rack>], item: &CatalogItem, placements: &[Placement], received_at_us: u64) {
+    for placement in placements {
+        let rack = &mut *racks[placement.location.rack];
+        rack.bin_mut(placement.location).add(item, placement.quantity, received_at_us);
+        rack.capacity += placement.quantity;
+    }
+}
+
+// One strategy's results over the simulated orders
+#[derive(Debug, Clone, Serialize)]
+pub struct Comparison {
+    pub strategy: &'static str,
+    pub supplies_stored: usize,
+    pub supplies_rejected: usize,
+    pub offloads: usize,
+    pub putaway_travel_m: f64,  // Round trips from receiving to each bin filled
+    pub pick_travel_m: f64,     // Round trips from shipping to each bin picked
+    pub mean_fill_ratio: f64,
+    pub max_fill_ratio: f64,
+}
+
+// Run the same seeded order stream through every strategy on the demo racks, without a broker.
+// Demand is skewed towards the first catalog items so velocity zoning has fast and slow movers to sort.
+pub fn compare(orders: usize, seed: u64) -> Vec<Comparison> {
+    PUTAWAY_KINDS.iter().map(|kind| {
+        let strategy = kind.strategy();
+        let racks = RackTable::new(inventory_management::demo_racks());
+        let mut items: Vec<Item> = inventory_management::opening_stock();
+        let mut result = Comparison {
+            strategy: strategy.name(), supplies_stored: 0, supplies_rejected: 0, offloads: 0,
+            putaway_travel_m: 0.0, pick_travel_m: 0.0, mean_fill_ratio: 0.0, max_fill_ratio: 0.0,
+        };
+        racks.with_racks(|racks| {
+            inventory_management::rack_opening_stock(racks, strategy.as_ref(), &items, 0);
+            let mut rng = StdRng::seed_from_u64(seed);
+            let weights: Vec<f64> = (0..items.len()).map(|i| 1.0 / (i + 1) as f64).collect();
+            let total_weight: f64 = weights.iter().sum();
+            for order in 0..orders {
+                let now_us = order as u64 * 60_000_000;    // An order a minute, for lot receipt times
+                let mut pick = rng.gen_range(0.0..total_weight);
+                let index = weights.iter().position(|w| { pick -= w; pick < 0.0 }).unwrap_or(0);
+                let quantity = rng.gen_range(100..500);
+                let supply = rng.gen_bool(0.5);
+                let Some(catalog_item) = catalog().get(&items[index].code) else { continue };
+                if supply {
+                    let velocity = if strategy.uses_velocity() { Velocity::of(&catalog_item.sku, &items) } else { Velocity::B };
+                    match plan(racks, strategy.as_ref(), catalog_item, velocity, quantity) {
+                        Ok(placements) => {
+                            apply(racks, catalog_item, &placements, now_us);
+                            result.putaway_travel_m += placements.iter()
+                                .map(|p| 2.0 * racks[p.location.rack].receiving_distance_m(p.location)).sum::<f64>();
+                            items[index].quantity += quantity;
+                            items[index].entry += quantity;
+                            result.supplies_stored += 1;
+                        }
+                        Err(_) => result.supplies_rejected += 1,
+                    }
+                } else if items[index].quantity >= quantity {
+                    let strategy = pick::for_category(&catalog_item.category).strategy();
+                    let picks = pick::execute(racks, strategy.as_ref(), catalog_item, &[], &[], quantity, now_us);
+                    result.pick_travel_m += picks.iter()
+                        .map(|p| 2.0 * racks[p.location.rack].shipping_distance_m(p.location)).sum::<f64>();
+                    items[index].quantity -= quantity;
+                    items[index].exit += quantity;
+                    result.offloads += 1;
+                }
+            }
+            let fills: Vec<f64> = racks.iter().map(|rack| rack.fill_ratio()).collect();
+            result.mean_fill_ratio = fills.iter().sum::<f64>() / fills.len().max(1) as f64;
+            result.max_fill_ratio = fills.iter().copied().fold(0.0, f64::max);
+        });
+        result
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::{Dimensions, StorageRequirements};
+
+    // Racks A and B of one level of three 1 m bins; A is near shipping, B near receiving
+    fn racks() -> RackTable {
+        let bin = Dimensions { length_mm: 1000, width_mm: 1000, height_mm: 1000 };
+        let mut a = Rack::new("A", 1, 3, bin.clone(), 100.0);
+        let mut b = Rack::new("B", 1, 3, bin, 100.0);
+        (a.receiving_m, a.shipping_m) = (20.0, 5.0);
+        (b.receiving_m, b.shipping_m) = (5.0, 20.0);
+        RackTable::new(vec![a, b])
+    }
+
+    // Eight fit a bin by volume, ten by weight
+    fn item() -> CatalogItem {
+        CatalogItem {
+            sku: "001".to_string(),
+            description: "Box".to_string(),
+            category: String::new(),
+            packs: Default::default(),
+            dimensions: Dimensions { length_mm: 500, width_mm: 500, height_mm: 500 },
+            weight_kg: 10.0,
+            storage: StorageRequirements::default(),
+            shelf_life_days: None,
+        }
+    }
+
+    fn at(rack: usize, bin: usize) -> Location {
+        Location { rack, level: 0, bin }
+    }
+
+    fn first_bin(kind: PutawayKind, velocity: Velocity, racks: &RackTable) -> Location {
+        racks.with_racks(|racks| plan(racks, kind.strategy().as_ref(), &item(), velocity, 1).unwrap()[0].location)
+    }
+
+    #[test]
+    fn first_fit_fills_bins_in_rack_order() {
+        let placements = racks().with_racks(|racks| plan(racks, &FirstFit, &item(), Velocity::B, 10).unwrap());
+        assert_eq!(placements, vec![Placement { location: at(0, 0), quantity: 8 }, Placement { location: at(0, 1), quantity: 2 }]);
+    }
+
+    #[test]
+    fn closest_to_receiving_starts_at_the_nearest_rack() {
+        assert_eq!(first_bin(PutawayKind::ClosestToReceiving, Velocity::B, &racks()), at(1, 0));
+    }
+
+    #[test]
+    fn consolidate_same_sku_tops_up_a_bin_holding_it() {
+        let racks = racks();
+        racks.with_racks(|racks| apply(racks, &item(), &[Placement { location: at(1, 2), quantity: 3 }], 0));
+        assert_eq!(first_bin(PutawayKind::ConsolidateSameSku, Velocity::B, &racks), at(1, 2));
+    }
+
+    #[test]
+    fn abc_velocity_zones_by_distance_from_shipping() {
+        let racks = racks();
+        assert_eq!(first_bin(PutawayKind::AbcVelocity, Velocity::A, &racks), at(0, 0));
+        assert_eq!(first_bin(PutawayKind::AbcVelocity, Velocity::B, &racks), at(1, 0));
+        assert_eq!(first_bin(PutawayKind::AbcVelocity, Velocity::C, &racks), at(1, 2));
+        assert!(PutawayKind::AbcVelocity.strategy().uses_velocity());
+        assert!(!PutawayKind::FirstFit.strategy().uses_velocity());
+    }
+
+    #[test]
+    fn spread_for_balance_starts_at_the_emptiest_rack() {
+        let racks = racks();
+        racks.with_racks(|racks| apply(racks, &item(), &[Placement { location: at(0, 0), quantity: 1 }], 0));
+        assert_eq!(first_bin(PutawayKind::SpreadForBalance, Velocity::B, &racks), at(1, 0));
+    }
+
+    #[test]
+    fn nothing_is_planned_without_room_for_all_of_it() {
+        let result = racks().with_racks(|racks| plan(racks, &FirstFit, &item(), Velocity::B, 100));
+        assert!(matches!(result, Err(PutawayError::NoSpace { requested: 100, room: 48 })));
+    }
+
+    #[test]
+    fn unsuitable_items_are_refused() {
+        let mut hazardous = item();
+        hazardous.storage.hazardous = true;
+        let result = racks().with_racks(|racks| plan(racks, &FirstFit, &hazardous, Velocity::B, 1));
+        assert!(matches!(result, Err(PutawayError::Unsuitable(_))));
+
+        let mut too_long = item();
+        too_long.dimensions.length_mm = 1500;
+        let result = racks().with_racks(|racks| plan(racks, &FirstFit, &too_long, Velocity::B, 1));
+        assert!(matches!(result, Err(PutawayError::Unsuitable(_))));
+    }
+}
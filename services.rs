@@ -0,0 +1,186 @@
+use std::sync::Arc;
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::Duration;
+use clap::Parser;
+use crate::api::{self, ApiParts, OrderIntake};
+use crate::cli::ServiceArgs;
+use crate::catalog::{self, Catalog};
+use crate::codec;
+use crate::config::{Config, Runtime};
+use crate::dedup;
+use crate::events;
+use crate::goods_transportation::GoodsTransportation;
+use crate::inventory_management::{print_inventory_report, InventoryManagement};
+use crate::metrics;
+use crate::order_processing::Order;
+use crate::outbox;
+use crate::pick;
+use crate::rabbitmq;
+use crate::stock_rpc::{self, StockClient, StockService};
+use crate::topology;
+use crate::trip_rpc::{self, TripClaims, TripClient};
+
+// The parts of the warehouse that can run as separate processes, talking only through the broker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Service {
+    OrderIntake,            // Order generator and POST /orders, reserving stock over stock_queue; owns cancellation
+    TransportController,    // Forklifts: order_queue -> transport_queue, claiming each trip over trip_queue
+    InventoryService,       // Inventory updates, rack storage and stock requests
+    Reporter,               // Periodic report from inventory snapshots
+}
+
+impl Service {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Service::OrderIntake => "order_intake",
+            Service::TransportController => "transport_controller",
+            Service::InventoryService => "inventory_service",
+            Service::Reporter => "reporter",
+        }
+    }
+
+    // Ports and outbox file of its own, so the services can run side by side from one wms.json
+    pub fn defaults(&self) -> Config {
+        let (api_port, metrics_port) = match self {
+            Service::OrderIntake => (8081, 9899),
+            Service::TransportController => (8082, 9900),
+            Service::InventoryService => (8083, 9901),
+            Service::Reporter => (8084, 9902),
+        };
+        Config {
+            api_addr: format!("127.0.0.1:{}", api_port),
+            metrics_addr: format!("127.0.0.1:{}", metrics_port),
+            outbox_file: Some(format!("wms-outbox-{}.json", self.name())),
+            ..Config::default()
+        }
+    }
+}
+
+// Entry point of the binaries in bin/
+pub fn main(service: Service) {
+    let args = ServiceArgs::parse();
+    match load_config(Some(service), args.config.as_deref()) {
+        Ok(config) => run(service, &config),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run(service: Service, config: &Config) {
+    info!("services", "Starting {}", service.name());
+    if let Err(e) = prepare_broker(config) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    let mut parts = ApiParts { service: service.name(), orders: None, inventory: None, transport: None };
+    match service {
+        Service::OrderIntake => {
+            let stock: Arc<dyn StockService> = Arc::new(stock_client(config));
+            start_generator(config, Arc::clone(&stock), None);
+            parts.orders = Some(OrderIntake { queue: config.order_queue.clone(), stock });
+            trip_rpc::serve(config.trip_queue.clone());
+        }
+        Service::TransportController => parts.transport = Some(start_transport(config, Arc::new(trip_client(config)), None)),
+        Service::InventoryService => parts.inventory = Some(start_inventory(config)),
+        Service::Reporter => start_reporter(config),
+    }
+    metrics::serve(&config.metrics_addr, parts.inventory.clone(), parts.transport.clone());
+    api::serve(&config.api_addr, parts);
+
+    loop {
+        thread::sleep(Duration::from_secs(60));
+    }
+}
+
+// Validate the config and apply the process-wide settings
+pub fn load_config(service: Option<Service>, path: Option<&str>) -> Result<Config, String> {
+    let config = match service {
+        Some(service) => Config::load_service(service.name(), path, service.defaults())?,
+        None => Config::load(path)?,
+    };
+    let errors = config.validate();
+    if !errors.is_empty() {
+        return Err(format!("Invalid config:\n{}", errors.join("\n")));
+    }
+    // Each setting can only be set before its first use, so a getter that ran too early is an error here
+    rabbitmq::configure(&config.amqp_url, config.retry.clone())?;
+    dedup::configure(config.dedup.clone())?;
+    codec::configure(config.codecs.clone())?;
+    pick::configure(config.picking.clone())?;
+    if let Some(path) = &config.catalog_file {
+        catalog::configure(Catalog::load(path)?)?;
+    }
+    Ok(config)
+}
+
+// Shared by every service: topology, the event exchange and the outbox
+pub fn prepare_broker(config: &Config) -> Result<(), String> {
+    // Queues must exist with their arguments before anything is published to them
+    topology::declare_on_startup(&config.topology, &[&config.order_queue, &config.transport_queue, &config.stock_queue, &config.trip_queue])?;
+
+    // Publish warehouse events to the topic exchange for other services to subscribe to
+    events::forward_to_broker(config.topology.events_exchange.clone());
+    // and follow the order status changes made by the other services
+    events::follow_order_status(config.topology.events_exchange.clone());
+    // Dispatch queue depth for the metrics and the dashboard
+    metrics::follow_queue_depth(config.order_queue.clone());
+
+    // Retry publishes left unconfirmed, including any from a previous run
+    outbox::start(config.outbox_file.clone())
+}
+
+pub fn stock_client(config: &Config) -> StockClient {
+    StockClient::new(config.stock_queue.clone(), Duration::from_millis(config.stock_timeout_ms))
+}
+
+pub fn trip_client(config: &Config) -> TripClient {
+    TripClient::new(config.trip_queue.clone(), Duration::from_millis(config.trip_timeout_ms))
+}
+
+// Transport asks `claims` whether each order may still go on a trip
+pub fn start_transport(config: &Config, claims: Arc<dyn TripClaims>, seed: Option<u64>) -> Arc<GoodsTransportation> {
+    let goods_transportation = Arc::new(GoodsTransportation::with_config(&config.transport, seed));
+    match config.runtime {
+        Runtime::Threads => goods_transportation.start(config.order_queue.clone(), config.transport_queue.clone(), claims),
+        Runtime::Async => goods_transportation.start_async(config.order_queue.clone(), config.transport_queue.clone(), claims),
+    }
+    goods_transportation
+}
+
+pub fn start_inventory(config: &Config) -> Arc<InventoryManagement> {
+    //Create channel for storage management
+    let (order_tx, order_rx) = channel::<Order>();
+    let inventory_management = Arc::new(InventoryManagement::new());
+    match config.runtime {
+        Runtime::Threads => inventory_management.start(config.transport_queue.clone(), order_tx, config.batch.clone()),
+        Runtime::Async => inventory_management.start_async(config.transport_queue.clone(), order_tx, config.batch.clone()),
+    }
+    inventory_management.storage_management(order_rx, config.putaway);
+    stock_rpc::serve(config.stock_queue.clone(), Arc::clone(&inventory_management));
+    inventory_management
+}
+
+pub fn start_generator(config: &Config, stock: Arc<dyn StockService>, seed: Option<u64>) {
+    match config.runtime {
+        Runtime::Threads => Order::start(stock, config.order_queue.clone(), seed),
+        Runtime::Async => Order::start_async(stock, config.order_queue.clone(), seed),
+    }
+}
+
+// The reporter has no inventory of its own, so it asks the inventory service for a snapshot
+pub fn start_reporter(config: &Config) {
+    let client = stock_client(config);
+    let interval = Duration::from_secs(config.report_interval_secs);
+    thread::spawn(move || {
+        loop {
+            match client.snapshot() {
+                Ok(snapshot) => print_inventory_report(&snapshot.items, &snapshot.racks),
+                Err(e) => warn!("report", "Failed to fetch inventory snapshot: {}", e),
+            }
+            thread::sleep(interval);
+        }
+    });
+}
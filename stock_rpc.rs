@@ -0,0 +1,141 @@
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use serde::{Serialize, Deserialize};
+use crate::inventory_management::{InventoryManagement, Item, Rack};
+use crate::order_processing::Order;
+use crate::pick::Pick;
+use crate::rabbitmq::{receive_message, request, send_reply, Message};
+
+// Questions order intake asks the inventory service
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum StockRequest {
+    Check { order: Order },     // Could the order go ahead, without holding stock for it
+    Reserve { order: Order },   // Check and hold the stock of an offload until it is applied
+    Release { order: String },  // Give back the stock held for an order id
+    Snapshot,                   // Current items and racks, answered with an InventorySnapshot
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StockReply {
+    pub ok: bool,
+    pub available: Option<i32>,     // Stock not yet reserved; None for unknown items
+    #[serde(default)]
+    pub picks: Vec<Pick>,           // Pick list of a reserved offload
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InventorySnapshot {
+    pub items: Vec<Item>,
+    pub racks: Vec<Rack>,
+}
+
+// How order intake reaches inventory: directly in one process, or over the broker when split up
+pub trait StockService: Send + Sync {
+    // Attaches the pick list to accepted offloads
+    fn reserve(&self, order: &mut Order) -> Result<bool, String>;
+    fn release(&self, order_id: &str) -> Result<(), String>;
+}
+
+impl StockService for InventoryManagement {
+    fn reserve(&self, order: &mut Order) -> Result<bool, String> {
+        Ok(self.reserve_stock(order))
+    }
+
+    fn release(&self, order_id: &str) -> Result<(), String> {
+        self.release_stock(order_id);
+        Ok(())
+    }
+}
+
+// Asks the inventory service over the broker, failing if it does not answer within the timeout
+pub struct StockClient {
+    queue: String,
+    timeout: Duration,
+}
+
+impl StockClient {
+    pub fn new(queue: String, timeout: Duration) -> Self {
+        StockClient { queue, timeout }
+    }
+
+    fn call(&self, stock_request: &StockRequest) -> Result<StockReply, String> {
+        let body = serde_json::to_string(stock_request).expect("Failed to serialize stock request");
+        let reply = request(&self.queue, &body, self.timeout)?;
+        serde_json::from_str(&reply).map_err(|e| format!("Invalid stock reply: {}", e))
+    }
+
+    // For services such as the reporter that have no inventory of their own
+    pub fn snapshot(&self) -> Result<InventorySnapshot, String> {
+        let body = serde_json::to_string(&StockRequest::Snapshot).expect("Failed to serialize stock request");
+        let reply = request(&self.queue, &body, self.timeout)?;
+        serde_json::from_str(&reply).map_err(|e| format!("Invalid snapshot reply: {}", e))
+    }
+}
+
+impl StockService for StockClient {
+    fn reserve(&self, order: &mut Order) -> Result<bool, String> {
+        let reply = self.call(&StockRequest::Reserve { order: order.clone() })?;
+        order.picks = reply.picks;
+        Ok(reply.ok)
+    }
+
+    fn release(&self, order_id: &str) -> Result<(), String> {
+        self.call(&StockRequest::Release { order: order_id.to_string() }).map(|_| ())
+    }
+}
+
+// Answer stock requests from the queue on behalf of the inventory service
+pub fn serve(queue: String, inventory_management: Arc<InventoryManagement>) {
+    thread::spawn(move || {
+        loop {
+            let message = receive_message(&queue);
+            let (reply_to, correlation_id) = match (&message.reply_to, &message.correlation_id) {
+                (Some(reply_to), Some(correlation_id)) => (reply_to.clone(), correlation_id.clone()),
+                _ => {
+                    message.dead_letter("Stock request without reply_to or correlation_id");
+                    continue;
+                }
+            };
+            let stock_request = match message.decode::<StockRequest>() {
+                Ok(stock_request) => stock_request,
+                Err(e) => {
+                    message.dead_letter(&format!("Failed to deserialize stock request: {}", e));
+                    continue;
+                }
+            };
+            let reply = match &stock_request {
+                StockRequest::Snapshot => {
+                    let snapshot = InventorySnapshot { items: inventory_management.inventory_snapshot(), racks: inventory_management.rack_snapshot() };
+                    answer(message, &reply_to, &correlation_id, &serde_json::to_string(&snapshot).expect("Failed to serialize snapshot"));
+                    continue;
+                }
+                StockRequest::Check { order } => StockReply {
+                    ok: inventory_management.check_inventory_stock(order),
+                    available: inventory_management.available_stock(&order.code),
+                    picks: Vec::new(),
+                },
+                StockRequest::Reserve { order } => {
+                    let mut order = order.clone();
+                    let ok = inventory_management.reserve(&mut order).unwrap_or(false);
+                    StockReply { ok, available: inventory_management.available_stock(&order.code), picks: order.picks }
+                }
+                StockRequest::Release { order } => {
+                    inventory_management.release_stock(order);
+                    StockReply { ok: true, available: None, picks: Vec::new() }
+                }
+            };
+            answer(message, &reply_to, &correlation_id, &serde_json::to_string(&reply).expect("Failed to serialize stock reply"));
+        }
+    });
+}
+
+fn answer(message: Message, reply_to: &str, correlation_id: &str, body: &str) {
+    if let Err(e) = send_reply(reply_to, correlation_id, body) {
+        warn!("stock_rpc", "Failed to reply to stock request: {}", e);  // The caller times out and retries
+    }
+    if let Err(e) = message.ack() {
+        error!("stock_rpc", "Failed to ack stock request: {}", e);
+    }
+}
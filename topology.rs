@@ -0,0 +1,104 @@
+use amiquip::{AmqpValue, Connection, Error, ExchangeDeclareOptions, ExchangeType, FieldTable, QueueDeclareOptions, Result};
+use std::thread;
+use crate::config::{QueueArgs, TopologyConfig};
+use crate::rabbitmq::{amqp_url, dead_letter_queue, retry_policy};
+
+// AMQP reply codes
+const CONNECTION_FORCED: u16 = 320;     // The broker is shutting down
+const PRECONDITION_FAILED: u16 = 406;
+
+// Declare the topology before anything publishes or consumes, retrying until the broker is reachable.
+// A declaration the broker refuses fails for good, as retrying cannot change its answer.
+pub fn declare_on_startup(topology: &TopologyConfig, queues: &[&str]) -> std::result::Result<(), String> {
+    let mut attempt = 0;
+    loop {
+        match declare(topology, queues) {
+            Ok(()) => {
+                info!("topology", "Declared exchanges {} and {} and {} queue(s)",
+                        topology.events_exchange, topology.dead_letter_exchange, queue_names(topology, queues).len());
+                return Ok(());
+            }
+            Err(e) if is_refusal(&e) => {
+                error!("topology", "Broker refused the topology: {}", e);
+                let hint = match e {
+                    Error::ServerClosedChannel { code: PRECONDITION_FAILED, .. } => " A queue or exchange already exists with other \
+                        arguments; delete it on the broker or put its old arguments back in the config.",
+                    _ => "",
+                };
+                return Err(format!("Broker refused the topology: {}.{}", e, hint));
+            }
+            Err(e) => {
+                attempt += 1;
+                error!("topology", "Failed to declare broker topology (attempt {}): {}", attempt, e);
+                thread::sleep(retry_policy().backoff(attempt));
+            }
+        }
+    }
+}
+
+// Closed by the broker over what was asked rather than lost on the way, e.g. 406 PRECONDITION_FAILED
+fn is_refusal(e: &Error) -> bool {
+    match e {
+        Error::ServerClosedChannel { .. } => true,
+        Error::ServerClosedConnection { code, .. } => *code != CONNECTION_FORCED,
+        _ => false,
+    }
+}
+
+// Durable exchanges and queues with their arguments and bindings. Declarations are idempotent,
+// so running this again is harmless as long as the arguments have not changed.
+pub fn declare(topology: &TopologyConfig, queues: &[&str]) -> Result<()> {
+    let mut connection = Connection::insecure_open(amqp_url())?;
+    let channel = connection.open_channel(None)?;
+    let exchange_options = ExchangeDeclareOptions { durable: true, ..ExchangeDeclareOptions::default() };
+    let events = channel.exchange_declare(ExchangeType::Topic, topology.events_exchange.as_str(), exchange_options.clone())?;
+    let dlx = channel.exchange_declare(ExchangeType::Direct, topology.dead_letter_exchange.as_str(), exchange_options)?;
+
+    for name in queue_names(topology, queues) {
+        let args = topology.queues.get(&name).cloned().unwrap_or_default();
+        let options = QueueDeclareOptions { durable: true, arguments: arguments(topology, &name, &args), ..QueueDeclareOptions::default() };
+        channel.queue_declare(name.as_str(), options)?;
+        if args.dead_letter {
+            let dlq = dead_letter_queue(&name);
+            let queue = channel.queue_declare(dlq.as_str(), durable())?;
+            queue.bind(&dlx, dlq.as_str(), FieldTable::new())?;
+        }
+    }
+    for binding in &topology.bindings {
+        let queue = channel.queue_declare_passive(binding.queue.as_str())?;
+        queue.bind(&events, binding.routing_key.as_str(), FieldTable::new())?;
+    }
+    connection.close()
+}
+
+// Options for queues declared outside the topology config, such as dead-letter queues
+pub fn durable() -> QueueDeclareOptions {
+    QueueDeclareOptions { durable: true, ..QueueDeclareOptions::default() }
+}
+
+// The systems' own queues first, then any configured extras, each once
+fn queue_names(topology: &TopologyConfig, queues: &[&str]) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    let configured = topology.queues.keys().map(|q| q.as_str()).chain(topology.bindings.iter().map(|b| b.queue.as_str()));
+    for name in queues.iter().copied().chain(configured) {
+        if !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+    }
+    names
+}
+
+fn arguments(topology: &TopologyConfig, queue_name: &str, args: &QueueArgs) -> FieldTable {
+    let mut table = FieldTable::new();
+    if let Some(ttl) = args.message_ttl_ms {
+        table.insert("x-message-ttl".to_string(), AmqpValue::LongLongInt(ttl as i64));
+    }
+    if let Some(max_length) = args.max_length {
+        table.insert("x-max-length".to_string(), AmqpValue::LongLongInt(max_length as i64));
+    }
+    if args.dead_letter {
+        table.insert("x-dead-letter-exchange".to_string(), AmqpValue::LongString(topology.dead_letter_exchange.clone()));
+        table.insert("x-dead-letter-routing-key".to_string(), AmqpValue::LongString(dead_letter_queue(queue_name)));
+    }
+    table
+}
@@ -0,0 +1,91 @@
+use std::thread;
+use std::time::Duration;
+use serde::{Serialize, Deserialize};
+use crate::order_processing::Order;
+use crate::order_registry::{registry, OrderStatus};
+use crate::rabbitmq::{receive_message, request, send_reply, Message};
+
+// Transport claims each order from order intake before starting its trip. Intake owns cancellation,
+// so an order is either cancelled there or claimed for a trip, never both.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TripRequest {
+    pub order: Order,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TripReply {
+    pub ok: bool,   // False if the order was cancelled
+}
+
+// How transport reaches order intake: directly in one process, or over the broker when split up
+pub trait TripClaims: Send + Sync {
+    // Whether the order may go on a trip; it is in transit from then on and can no longer be cancelled
+    fn claim(&self, order: &Order) -> Result<bool, String>;
+}
+
+// Order intake's own registry, for all services in one process
+pub struct LocalClaims;
+
+impl TripClaims for LocalClaims {
+    fn claim(&self, order: &Order) -> Result<bool, String> {
+        Ok(registry().start_trip(order))
+    }
+}
+
+// Asks order intake over the broker, failing if it does not answer within the timeout
+pub struct TripClient {
+    queue: String,
+    timeout: Duration,
+}
+
+impl TripClient {
+    pub fn new(queue: String, timeout: Duration) -> Self {
+        TripClient { queue, timeout }
+    }
+}
+
+impl TripClaims for TripClient {
+    fn claim(&self, order: &Order) -> Result<bool, String> {
+        let body = serde_json::to_string(&TripRequest { order: order.clone() }).expect("Failed to serialize trip request");
+        let reply = request(&self.queue, &body, self.timeout)?;
+        let reply: TripReply = serde_json::from_str(&reply).map_err(|e| format!("Invalid trip reply: {}", e))?;
+        // Intake publishes the change itself; record it here without waiting for the event
+        let status = if reply.ok { OrderStatus::InTransit } else { OrderStatus::Cancelled };
+        registry().record(order, status);
+        Ok(reply.ok)
+    }
+}
+
+// Answer trip claims from the queue on behalf of order intake
+pub fn serve(queue: String) {
+    thread::spawn(move || {
+        loop {
+            let message = receive_message(&queue);
+            let (reply_to, correlation_id) = match (&message.reply_to, &message.correlation_id) {
+                (Some(reply_to), Some(correlation_id)) => (reply_to.clone(), correlation_id.clone()),
+                _ => {
+                    message.dead_letter("Trip request without reply_to or correlation_id");
+                    continue;
+                }
+            };
+            let trip_request = match message.decode::<TripRequest>() {
+                Ok(trip_request) => trip_request,
+                Err(e) => {
+                    message.dead_letter(&format!("Failed to deserialize trip request: {}", e));
+                    continue;
+                }
+            };
+            let reply = TripReply { ok: registry().start_trip(&trip_request.order) };
+            answer(message, &reply_to, &correlation_id, &serde_json::to_string(&reply).expect("Failed to serialize trip reply"));
+        }
+    });
+}
+
+fn answer(message: Message, reply_to: &str, correlation_id: &str, body: &str) {
+    if let Err(e) = send_reply(reply_to, correlation_id, body) {
+        warn!("trip_rpc", "Failed to reply to trip request: {}", e);  // The caller times out and asks again
+    }
+    if let Err(e) = message.ack() {
+        error!("trip_rpc", "Failed to ack trip request: {}", e);
+    }
+}